@@ -25,8 +25,8 @@ pub struct Camera {
     time1: f64
 }
 
-impl Camera {
-    pub fn default() -> Camera {
+impl Default for Camera {
+    fn default() -> Camera {
         Camera::custom(
             Vec3(0.0, 0.0, 0.0), 
             Vec3(0.0, 0.0, -1.0), 
@@ -39,7 +39,9 @@ impl Camera {
             0.0
         )
     }
+}
 
+impl Camera {
     pub fn custom(look_from: Vec3, 
                   look_at: Vec3, 
                   v_up: Vec3, 
@@ -1,6 +1,6 @@
-use clap::{Parser, clap_derive::ArgEnum, Args};
+use clap::{Parser, Args};
 
-use crate::preset_scenes::PresetScene;
+use raytrace::{preset_scenes::PresetScene, integrators::IntegratorKind, types::bvh::SplitStrategy, RenderStrategy};
 
 #[derive(Parser)]
 pub struct CliArguments {
@@ -14,6 +14,43 @@ pub struct CliArguments {
     pub output_file: Option<String>,
     #[clap(long="scene", arg_enum, value_parser, default_value_t=PresetScene::JumpingBalls)]
     pub preset_scene: PresetScene,
+    /// Scene file to render instead of a preset scene
+    #[clap(long="scene-file")]
+    pub scene_file: Option<String>,
+    /// How BVHs are split, overriding a scene file's `acceleration.split` (preset scenes default to `sah`)
+    #[clap(long="split", arg_enum, value_parser)]
+    pub split: Option<SplitStrategy>,
+    #[clap(flatten)]
+    pub integrator_settings: IntegratorSettings,
+}
+
+#[derive(Debug, Args)]
+pub struct IntegratorSettings {
+    /// How the color of each camera ray is worked out, either by path tracing or one of the debug views
+    #[clap(long="integrator", arg_enum, value_parser, default_value_t=IntegratorKind::Path)]
+    pub integrator: IntegratorKind,
+    /// Most bounces a path can take
+    #[clap(long="max-depth", default_value_t=64)]
+    pub max_depth: u32,
+    /// Most bounces off of diffuse surfaces a path can take, 0 gives direct lighting only
+    #[clap(long="max-diffuse-depth", default_value_t=16)]
+    pub max_diffuse_depth: u32,
+    /// Most reflections off of mirrors and glossy surfaces a path can take
+    #[clap(long="max-specular-depth", default_value_t=16)]
+    pub max_specular_depth: u32,
+    /// Most times a path can refract through glass and the like
+    #[clap(long="max-transmission-depth", default_value_t=32)]
+    pub max_transmission_depth: u32,
+    /// Most times a path can scatter inside fog, smoke, etc.
+    #[clap(long="max-volume-depth", default_value_t=64)]
+    pub max_volume_depth: u32,
+    /// Bounces after which paths carrying little light are ended early with Russian roulette
+    #[clap(long="roulette-depth", default_value_t=3)]
+    pub roulette_depth: u32,
+    /// Distance the `depth` view fades to black at, and how far `ambient-occlusion` rays reach
+    /// (defaults to the size of the scene, and a tenth of it)
+    #[clap(long="debug-distance")]
+    pub debug_distance: Option<f64>
 }
 
 #[derive(Debug, Args)]
@@ -24,11 +61,4 @@ pub struct MultithreadedSettings {
     pub render_strategy: RenderStrategy,
     #[clap(long="tile-size", default_value_t=64)]
     pub tile_size: u32
-}
-
-#[derive(Debug, Clone, ArgEnum, PartialEq)]
-pub enum RenderStrategy {
-    ProgressiveAverage,
-    TileFull,
-    TileAverage
 }
\ No newline at end of file
@@ -0,0 +1,479 @@
+use std::{collections::HashMap, fmt::Display, fs, io, path::{Path, PathBuf}, sync::Arc};
+
+use gltf::{
+    Gltf,
+    Node,
+    buffer::Source as BufferSource,
+    image::Source as ImageSource,
+    camera::Projection,
+    mesh::Mode,
+    texture::WrappingMode
+};
+use image::ImageError;
+use obj::IndexTuple;
+
+use crate::{
+    hittables::{
+        hittable::HitRecord,
+        mesh::{
+            Mesh,
+            MeshError
+        }
+    },
+    types::{
+        vec3::{
+            Point,
+            Vec3
+        },
+        vec2::{
+            UV,
+            Vec2
+        },
+        color::Color,
+        transform::{
+            TransformData,
+            Transform,
+            Matrix4x4
+        },
+        materials::{
+            Material,
+            NormalPerturbation,
+            principled::PrincipledBsdf
+        },
+        texture::{
+            Texture,
+            SolidColor,
+            ImageTexture
+        }
+    }
+};
+
+/// The contents of a glTF 2.0 file (.gltf or .glb), as meshes placed by the node hierarchy and cameras
+/// # Fields
+/// `instances` - every node of the default scene with a mesh, along with the node's transform in world space.
+/// A mesh used by several nodes is only loaded once
+///
+/// `cameras` - every node of the default scene with a perspective camera, in the order they're found
+pub struct GltfScene {
+    pub instances: Vec<(Arc<Mesh>, TransformData)>,
+    pub cameras: Vec<GltfCamera>
+}
+
+/// A camera placed by a glTF node, which looks down its node's -z axis with +y up
+/// # Fields
+/// `vfov` - vertical field of view, in degrees
+///
+/// `aspect_ratio` - width over height, if the file specifies one
+#[derive(Debug, Clone, Copy)]
+pub struct GltfCamera {
+    pub look_from: Point,
+    pub look_at: Point,
+    pub up: Vec3,
+    pub vfov: f64,
+    pub aspect_ratio: Option<f64>
+}
+
+#[derive(Debug)]
+pub enum GltfError {
+    Gltf(gltf::Error),
+    /// A buffer or image file couldn't be read. Holds the file's path
+    Io(PathBuf, io::Error),
+    Base64(base64::DecodeError),
+    Image(ImageError),
+    Mesh(MeshError),
+    /// The file is valid glTF, but refers to data that isn't there
+    Invalid(String)
+}
+
+impl Display for GltfError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GltfError::Gltf(error) => write!(f, "{}", error),
+            GltfError::Io(path, error) => write!(f, "unable to read '{}': {}", path.display(), error),
+            GltfError::Base64(error) => write!(f, "invalid data uri: {}", error),
+            GltfError::Image(error) => write!(f, "unable to load image: {}", error),
+            GltfError::Mesh(error) => write!(f, "{}", error),
+            GltfError::Invalid(message) => write!(f, "{}", message)
+        }
+    }
+}
+
+impl std::error::Error for GltfError {}
+
+impl From<gltf::Error> for GltfError {
+    fn from(x: gltf::Error) -> Self {
+        GltfError::Gltf(x)
+    }
+}
+
+impl From<MeshError> for GltfError {
+    fn from(x: MeshError) -> Self {
+        GltfError::Mesh(x)
+    }
+}
+
+/// A glTF texture input: `factor` times an optional image. Metallic and roughness share an image
+/// (in its blue and green channels), so `channel` picks out one of them. Base colors are multiplied
+/// by the mesh's vertex colors as well, if it has any. `wrap` is how the image repeats along u and v
+struct FactorTexture {
+    image: Option<Arc<ImageTexture>>,
+    wrap: [WrappingMode; 2],
+    channel: Option<usize>,
+    factor: Color,
+    vertex_colors: bool
+}
+
+/// Moves a texture coordinate outside of [0, 1] back into it, as `mode` repeats the image
+fn wrap(x: f64, mode: WrappingMode) -> f64 {
+    match mode {
+        // `ImageTexture` clamps by itself
+        WrappingMode::ClampToEdge => x,
+        WrappingMode::Repeat => x.rem_euclid(1.0),
+        WrappingMode::MirroredRepeat => {
+            let x = x.rem_euclid(2.0);
+            if x > 1.0 { 2.0 - x } else { x }
+        }
+    }
+}
+
+impl Texture for FactorTexture {
+    fn value(&self, u: f64, v: f64, p: Point) -> Color {
+        let sample = match &self.image {
+            Some(image) => {
+                // v was flipped when the mesh was loaded, so it's flipped back to wrap it the way glTF does
+                let [wrap_s, wrap_t] = self.wrap;
+                image.value(wrap(u, wrap_s), 1.0 - wrap(1.0 - v, wrap_t), p)
+            },
+            None => Vec3(1.0, 1.0, 1.0)
+        };
+        let sample = match self.channel {
+            Some(channel) => Vec3(sample[channel], sample[channel], sample[channel]),
+            None => sample
+        };
+        self.factor * sample
+    }
+
+    fn value_at(&self, record: &HitRecord) -> Color {
+        let value = self.value(record.u, record.v, record.p);
+        match record.vertex_color {
+            Some(color) if self.vertex_colors => value * color,
+            _ => value
+        }
+    }
+}
+
+impl GltfScene {
+    /// Loads the default scene (or the first, if there's no default) of a .gltf or .glb file. Buffers and images
+    /// may be embedded, in data uris or in files relative to the glTF file.
+    ///
+    /// Metallic-roughness materials become a `PrincipledBsdf` (including the transmission and ior extensions,
+    /// and normal maps), and emissive materials become lights. Primitives without a material are grey.
+    /// Scene files sample the emissive triangles of a glTF object directly, like any other light
+    pub fn load(path: &Path) -> Result<GltfScene, GltfError> {
+        let gltf = Gltf::open(path)?;
+        let directory = path.parent().unwrap_or(Path::new(""));
+
+        let mut buffers = Vec::new();
+        for buffer in gltf.document.buffers() {
+            let data = match buffer.source() {
+                BufferSource::Bin => gltf.blob.clone()
+                    .ok_or_else(|| GltfError::Invalid("missing binary chunk".to_string()))?,
+                BufferSource::Uri(uri) => read_uri(uri, directory)?
+            };
+            if data.len() < buffer.length() {
+                return Err(GltfError::Invalid(format!("buffer {} is shorter than its length", buffer.index())));
+            }
+            buffers.push(data);
+        }
+
+        let scene = gltf.document.default_scene().or_else(|| gltf.document.scenes().next())
+            .ok_or_else(|| GltfError::Invalid("no scenes".to_string()))?;
+
+        let mut loader = Loader {
+            directory,
+            buffers,
+            images: HashMap::new(),
+            materials: HashMap::new(),
+            meshes: HashMap::new()
+        };
+        let mut loaded = GltfScene { instances: Vec::new(), cameras: Vec::new() };
+        for node in scene.nodes() {
+            loader.visit(node, TransformData::identity(), &mut loaded)?;
+        }
+        Ok(loaded)
+    }
+}
+
+/// Reads the contents of a (possibly data) uri, relative to `directory`
+fn read_uri(uri: &str, directory: &Path) -> Result<Vec<u8>, GltfError> {
+    if let Some(data) = uri.strip_prefix("data:") {
+        let (_, encoded) = data.split_once(";base64,")
+            .ok_or_else(|| GltfError::Invalid("data uris must be base64".to_string()))?;
+        return base64::decode(encoded).map_err(GltfError::Base64);
+    }
+    let path = directory.join(percent_decode(uri));
+    fs::read(&path).map_err(|error| GltfError::Io(path, error))
+}
+
+/// Replaces the escaped characters of a relative uri (e.g `%20`) with the characters themselves
+fn percent_decode(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes.get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], escaped) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            },
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// glTF matrices are column major, and ours are row major
+fn row_major(matrix: [[f32; 4]; 4]) -> Matrix4x4 {
+    let mut rows = [[0.0; 4]; 4];
+    for (column, values) in matrix.iter().enumerate() {
+        for (row, value) in values.iter().enumerate() {
+            rows[row][column] = *value as f64;
+        }
+    }
+    rows
+}
+
+/// Builds meshes and materials from the buffers of a glTF file, sharing everything used more than once
+struct Loader<'a> {
+    directory: &'a Path,
+    buffers: Vec<Vec<u8>>,
+    images: HashMap<usize, Arc<ImageTexture>>,
+    /// keyed by the index of the material, primitives without one use None
+    materials: HashMap<Option<usize>, Material>,
+    /// None for meshes without any triangles
+    meshes: HashMap<usize, Option<Arc<Mesh>>>
+}
+
+impl Loader<'_> {
+    /// Adds `node` and its children to `scene`, placing them by their transforms after `parent`'s
+    fn visit(&mut self, node: Node, parent: TransformData, scene: &mut GltfScene) -> Result<(), GltfError> {
+        // a node scaled to nothing (usually to hide it) can't be inverted, and wouldn't be seen anyway
+        let transform = match TransformData::from_matrix(row_major(node.transform().matrix())) {
+            Some(local) => local.compose(parent),
+            None => return Ok(())
+        };
+
+        if let Some(mesh) = node.mesh() {
+            if let Some(mesh) = self.mesh(mesh)? {
+                scene.instances.push((mesh, transform));
+            }
+        }
+        if let Some(camera) = node.camera() {
+            // orthographic cameras aren't supported
+            if let Projection::Perspective(perspective) = camera.projection() {
+                scene.cameras.push(GltfCamera {
+                    look_from: Vec3(0.0, 0.0, 0.0).transform(transform),
+                    look_at: Vec3(0.0, 0.0, -1.0).transform(transform),
+                    up: transform.vector(Vec3(0.0, 1.0, 0.0)),
+                    vfov: (perspective.yfov() as f64).to_degrees(),
+                    aspect_ratio: perspective.aspect_ratio().map(|ratio| ratio as f64)
+                });
+            }
+        }
+
+        for child in node.children() {
+            self.visit(child, transform, scene)?;
+        }
+        Ok(())
+    }
+
+    /// Combines the triangles of every primitive of `mesh` into one `Mesh`, each with the material of its primitive
+    fn mesh(&mut self, mesh: gltf::Mesh) -> Result<Option<Arc<Mesh>>, GltfError> {
+        if let Some(built) = self.meshes.get(&mesh.index()) {
+            return Ok(built.clone());
+        }
+
+        let mut vertices = Vec::new();
+        let mut normals = Vec::new();
+        let mut uv_coords = Vec::new();
+        let mut colors = Vec::new();
+        let mut has_colors = false;
+        let mut polygons = Vec::new();
+        let mut materials = Vec::new();
+        let mut material_indices: HashMap<Option<usize>, usize> = HashMap::new();
+
+        for primitive in mesh.primitives() {
+            let buffers = &self.buffers;
+            let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice));
+            let positions: Vec<Vec3> = match reader.read_positions() {
+                Some(positions) => positions.map(|[x, y, z]| Vec3(x as f64, y as f64, z as f64)).collect(),
+                None => continue
+            };
+            let count = positions.len();
+            let primitive_normals: Option<Vec<Vec3>> = reader.read_normals()
+                .map(|normals| normals.map(|[x, y, z]| Vec3(x as f64, y as f64, z as f64)).collect());
+            // glTF texture coordinates start at the top left of the image, and ours at the bottom left
+            let primitive_uvs: Option<Vec<UV>> = reader.read_tex_coords(0)
+                .map(|uvs| uvs.into_f32().map(|[u, v]| UV(Vec2(u as f64, 1.0 - v as f64))).collect());
+            let primitive_colors: Option<Vec<Color>> = reader.read_colors(0)
+                .map(|colors| colors.into_rgb_f32().map(|[r, g, b]| Vec3(r as f64, g as f64, b as f64)).collect());
+            let indices: Vec<usize> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().map(|index| index as usize).collect(),
+                None => (0..count).collect()
+            };
+
+            let triangles: Vec<[usize; 3]> = match primitive.mode() {
+                Mode::Triangles => indices.chunks_exact(3).map(|triangle| [triangle[0], triangle[1], triangle[2]]).collect(),
+                // every other triangle of a strip is wound the other way round
+                Mode::TriangleStrip => (0..indices.len().saturating_sub(2)).map(|i| {
+                    if i % 2 == 0 { [indices[i], indices[i + 1], indices[i + 2]] }
+                    else { [indices[i + 1], indices[i], indices[i + 2]] }
+                }).collect(),
+                Mode::TriangleFan => (1..indices.len().saturating_sub(1))
+                    .map(|i| [indices[0], indices[i], indices[i + 1]])
+                    .collect(),
+                // points and lines have no area to hit
+                Mode::Points | Mode::Lines | Mode::LineLoop | Mode::LineStrip => continue
+            };
+            if let Some(&index) = indices.iter().find(|&&index| index >= count) {
+                return Err(GltfError::Invalid(format!("mesh {} refers to vertex {}, but there are only {}", mesh.index(), index, count)));
+            }
+
+            let key = primitive.material().index();
+            let material_index = match material_indices.get(&key) {
+                Some(&i) => i,
+                None => {
+                    materials.push(self.material(primitive.material())?);
+                    material_indices.insert(key, materials.len() - 1);
+                    materials.len() - 1
+                }
+            };
+
+            // every attribute is stored per vertex. Primitives missing one are padded, so they all stay lined up
+            let offset = vertices.len();
+            let has_normals = primitive_normals.as_ref().is_some_and(|normals| normals.len() == count);
+            let has_uvs = primitive_uvs.as_ref().is_some_and(|uvs| uvs.len() == count);
+            vertices.extend(positions);
+            match primitive_normals {
+                Some(primitive_normals) if has_normals => normals.extend(primitive_normals),
+                _ => normals.extend(std::iter::repeat_n(Vec3(0.0, 0.0, 0.0), count))
+            }
+            match primitive_uvs {
+                Some(primitive_uvs) if has_uvs => uv_coords.extend(primitive_uvs),
+                _ => uv_coords.extend(std::iter::repeat_n(UV(Vec2(0.0, 0.0)), count))
+            }
+            match primitive_colors {
+                Some(primitive_colors) if primitive_colors.len() == count => {
+                    has_colors = true;
+                    colors.extend(primitive_colors);
+                },
+                _ => colors.extend(std::iter::repeat_n(Vec3(1.0, 1.0, 1.0), count))
+            }
+
+            for triangle in triangles {
+                let corners = triangle.iter().map(|&index| {
+                    let index = offset + index;
+                    IndexTuple(index, has_uvs.then_some(index), has_normals.then_some(index))
+                }).collect();
+                polygons.push((corners, material_index));
+            }
+        }
+
+        let built = if polygons.is_empty() {
+            None
+        }
+        else {
+            // glTF asks for flat shading when a primitive doesn't have normals
+            let colors = if has_colors { Some(colors) } else { None };
+            Some(Arc::new(Mesh::from_polygons(vertices, normals, uv_coords, colors, polygons, materials, 0.0)?))
+        };
+        self.meshes.insert(mesh.index(), built.clone());
+        Ok(built)
+    }
+
+    fn material(&mut self, material: gltf::Material) -> Result<Material, GltfError> {
+        if let Some(built) = self.materials.get(&material.index()) {
+            return Ok(built.clone());
+        }
+        let color = |value: [f32; 3]| Vec3(value[0] as f64, value[1] as f64, value[2] as f64);
+
+        let built = if material.index().is_none() {
+            Material::Lambertian { albedo: Arc::new(SolidColor::from(Vec3(0.8, 0.8, 0.8))) }
+        }
+        else {
+            let emit = color(material.emissive_factor()) * material.emissive_strength().unwrap_or(1.0) as f64;
+            if emit.0 > 0.0 || emit.1 > 0.0 || emit.2 > 0.0 {
+                Material::DiffuseLight { emit: self.texture(material.emissive_texture().map(|info| info.texture()), None, emit, false)? }
+            }
+            else {
+                let pbr = material.pbr_metallic_roughness();
+                let [r, g, b, _] = pbr.base_color_factor();
+                let mut bsdf = PrincipledBsdf::new(self.texture(pbr.base_color_texture().map(|info| info.texture()), None, color([r, g, b]), true)?);
+                let metallic = pbr.metallic_factor() as f64;
+                let roughness = pbr.roughness_factor() as f64;
+                bsdf.metallic = self.texture(pbr.metallic_roughness_texture().map(|info| info.texture()), Some(2), Vec3(metallic, metallic, metallic), false)?;
+                bsdf.roughness = self.texture(pbr.metallic_roughness_texture().map(|info| info.texture()), Some(1), Vec3(roughness, roughness, roughness), false)?;
+                if let Some(transmission) = material.transmission() {
+                    let factor = transmission.transmission_factor() as f64;
+                    bsdf.transmission = self.texture(transmission.transmission_texture().map(|info| info.texture()), Some(0), Vec3(factor, factor, factor), false)?;
+                }
+                bsdf.index_of_refraction = material.ior().unwrap_or(1.5) as f64;
+
+                let bsdf = Material::Principled(Box::new(bsdf));
+                match material.normal_texture() {
+                    Some(normal) => Material::Perturbed {
+                        material: Box::new(bsdf),
+                        // `scale` really scales the mapped normal's x and y, but blending agrees with it at 0 and 1
+                        perturbation: NormalPerturbation::NormalMap {
+                            texture: self.texture(Some(normal.texture()), None, Vec3(1.0, 1.0, 1.0), false)?,
+                            strength: normal.scale() as f64
+                        }
+                    },
+                    None => bsdf
+                }
+            }
+        };
+        self.materials.insert(material.index(), built.clone());
+        Ok(built)
+    }
+
+    fn texture(&mut self, texture: Option<gltf::Texture>, channel: Option<usize>, factor: Color, vertex_colors: bool) -> Result<Arc<dyn Texture>, GltfError> {
+        let (image, wrap) = match texture {
+            Some(texture) => {
+                let sampler = texture.sampler();
+                (Some(self.image(texture.source())?), [sampler.wrap_s(), sampler.wrap_t()])
+            },
+            None => (None, [WrappingMode::ClampToEdge; 2])
+        };
+        Ok(Arc::new(FactorTexture { image, wrap, channel, factor, vertex_colors }))
+    }
+
+    fn image(&mut self, image: gltf::Image) -> Result<Arc<ImageTexture>, GltfError> {
+        if let Some(loaded) = self.images.get(&image.index()) {
+            return Ok(loaded.clone());
+        }
+        let data = match image.source() {
+            ImageSource::View { view, .. } => {
+                let buffer = &self.buffers[view.buffer().index()];
+                buffer.get(view.offset()..view.offset() + view.length())
+                    .ok_or_else(|| GltfError::Invalid(format!("image {} is outside of its buffer", image.index())))?
+                    .to_vec()
+            },
+            ImageSource::Uri { uri, .. } => read_uri(uri, self.directory)?
+        };
+        let decoded = image::load_from_memory(&data).map_err(GltfError::Image)?;
+        let loaded = Arc::new(ImageTexture {
+            width: decoded.width(),
+            height: decoded.height(),
+            data: decoded
+        });
+        self.images.insert(image.index(), loaded.clone());
+        Ok(loaded)
+    }
+}
\ No newline at end of file
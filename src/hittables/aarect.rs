@@ -1,10 +1,14 @@
 use crate::{
-    Material, 
     types::{
+        materials::Material, 
         aabb::AABB, 
-        vec3::Vec3, 
+        vec3::{
+            Vec3, 
+            Point
+        }, 
         ray::Ray
-    }
+    }, 
+    utils::random_range
 };
 use super::hittable::{
     Hit, 
@@ -58,7 +62,12 @@ macro_rules! aarect {
                 outward_normal.$third_axis = 1.0;
                 let u = (a - self.a0) / (self.a1 - self.a0);
                 let v = (b - self.b0) / (self.b1 - self.b0);
-                let hit_record = HitRecord::construct(r.at(t), outward_normal, t, r, &self.material, u, v);
+                let mut tangent = Vec3(0.0, 0.0, 0.0);
+                let mut bitangent = Vec3(0.0, 0.0, 0.0);
+                tangent.$first_axis = self.a1 - self.a0;
+                bitangent.$second_axis = self.b1 - self.b0;
+                let hit_record = HitRecord::construct(r.at(t), outward_normal, t, r, &self.material, u, v)
+                    .with_tangents(tangent, bitangent);
                 Some(hit_record)
             }
 
@@ -69,13 +78,36 @@ macro_rules! aarect {
                 min.$first_axis = self.a0;
                 min.$second_axis = self.b0;
                 min.$third_axis = self.k - 0.0001;
-                max.$first_axis = self.a0;
-                max.$second_axis = self.b0;
+                max.$first_axis = self.a1;
+                max.$second_axis = self.b1;
                 max.$third_axis = self.k + 0.0001;
                 Some(
                     AABB::new(min, max)
                 )
             }
+
+            /// Converts the area density of picking a point uniformly on the rectangle 
+            /// into a density with respect to solid angle as seen from `origin`
+            fn pdf_value(&self, origin: Point, direction: Vec3) -> f64 {
+                let r = Ray { origin, direction, time: 0.0 };
+                if let Some(record) = self.hit(r, 0.001, f64::INFINITY) {
+                    let area = (self.a1 - self.a0) * (self.b1 - self.b0);
+                    let distance_squared = record.t * record.t * direction.square_magnitude();
+                    let cosine = (direction.$third_axis / direction.length()).abs();
+                    distance_squared / (cosine * area)
+                }
+                else {
+                    0.0
+                }
+            }
+
+            fn random(&self, origin: Point) -> Vec3 {
+                let mut random_point = Vec3(0.0, 0.0, 0.0);
+                random_point.$first_axis = random_range(self.a0, self.a1);
+                random_point.$second_axis = random_range(self.b0, self.b1);
+                random_point.$third_axis = self.k;
+                random_point - origin
+            }
         }
     };
 }
@@ -13,7 +13,8 @@ use super::{
 use crate::{
     types::{
         vec3::{
-            Point
+            Point, 
+            Vec3
         }, 
         ray::Ray, 
         aabb::AABB, 
@@ -38,6 +39,8 @@ pub struct Block {
 impl Block {
     /// Create a new block with given material
     pub fn new(min: Point, max: Point, material: Material) -> Block {
+        // every face is the same material
+        let material = material.shared();
         let back = XY::new(
             material.clone(),
             min.x(),
@@ -105,4 +108,10 @@ impl Hit for Block {
     fn bounding_box(&self, _t0: f64, _t1: f64) -> Option<AABB> {
         Some(AABB::new(self.min, self.max))
     }
+    fn pdf_value(&self, origin: Point, direction: Vec3) -> f64 {
+        self.sides.pdf_value(origin, direction)
+    }
+    fn random(&self, origin: Point) -> Vec3 {
+        self.sides.random(origin)
+    }
 }
\ No newline at end of file
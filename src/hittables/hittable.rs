@@ -1,4 +1,6 @@
-use crate::types::{vec3::{Point, Vec3}, ray::Ray, materials::Material, aabb::AABB};
+use std::sync::Arc;
+
+use crate::types::{vec3::{Point, Vec3}, color::Color, ray::Ray, materials::Material, aabb::AABB, onb::Onb};
 
 /// Any type that implements Hit can be Hit by 
 /// In addition, these must be safe to send across threads 
@@ -7,6 +9,37 @@ pub trait Hit: Send + Sync {
     fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
     /// Returns a bounding box around this hittable from time t0 to t1
     fn bounding_box(&self, t0: f64, t1: f64) -> Option<AABB>;
+
+    /// Probability density (with respect to solid angle) of `random` returning `direction` 
+    /// when called from `origin`. Hittables which can't be sampled (the default) always return 0
+    fn pdf_value(&self, _origin: Point, _direction: Vec3) -> f64 {
+        0.0
+    }
+
+    /// Returns a random direction from `origin` towards this hittable (not necessarily normalized),
+    /// used for sampling lights directly. Only meaningful if `pdf_value` is implemented as well
+    fn random(&self, _origin: Point) -> Vec3 {
+        Vec3(1.0, 0.0, 0.0)
+    }
+}
+
+/// Lets one hittable be in several places at once, e.g in both the world and a scene's lights
+impl<T: Hit + ?Sized> Hit for Arc<T> {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        (**self).hit(r, t_min, t_max)
+    }
+
+    fn bounding_box(&self, t0: f64, t1: f64) -> Option<AABB> {
+        (**self).bounding_box(t0, t1)
+    }
+
+    fn pdf_value(&self, origin: Point, direction: Vec3) -> f64 {
+        (**self).pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: Point) -> Vec3 {
+        (**self).random(origin)
+    }
 }
 
 /// A HitRecord bundles together information about a ray hitting something that implements Hit
@@ -25,7 +58,12 @@ pub trait Hit: Send + Sync {
 /// so this should be a non-issue
 /// 
 /// `u`, `v` - the uv coordinates of the hit. Used for texture mapping
-#[derive(Debug)]
+/// 
+/// `tangent`, `bitangent` - the derivatives of `p` with respect to `u` and `v` (not normalized), or zero for
+/// hittables without them. Used with `normal` as a tangent frame for normal and bump mapping, see `tangent_frame`
+/// 
+/// `vertex_color` - color interpolated from the vertices of meshes which have them, read by `VertexColorTexture`
+#[derive(Debug, Clone, Copy)]
 pub struct HitRecord<'a> {
     pub p: Point,
     pub normal: Vec3,
@@ -33,7 +71,10 @@ pub struct HitRecord<'a> {
     pub front_face: bool,
     pub material: &'a Material,
     pub u: f64,
-    pub v: f64
+    pub v: f64,
+    pub tangent: Vec3,
+    pub bitangent: Vec3,
+    pub vertex_color: Option<Color>
 }
 
 impl HitRecord<'_> {
@@ -42,8 +83,10 @@ impl HitRecord<'_> {
     pub fn construct<'a>(p: Point, outward_normal: Vec3, t: f64, ray: Ray, material: &'a Material, u: f64, v: f64) -> HitRecord<'a> {
         let front_face = Vec3::dot(ray.direction, outward_normal) < 0.0;
         let normal = if front_face { outward_normal } else { -outward_normal };
+        let tangent = Vec3(0.0, 0.0, 0.0);
+        let bitangent = Vec3(0.0, 0.0, 0.0);
         
-        HitRecord { p: p, normal: normal, t: t, front_face: front_face, material, u, v }
+        HitRecord { p: p, normal: normal, t: t, front_face: front_face, material, u, v, tangent, bitangent, vertex_color: None }
     }
 
     /// Construct a hitrecord from a normal that was interpolated from vertex normals. Solves the problem of certain rays 
@@ -52,7 +95,31 @@ impl HitRecord<'_> {
     pub fn construct_from_interpolated_normal<'a>(p: Point, interpolated_normal: Vec3, front_face: bool, 
             t: f64, ray: Ray, material: &'a Material, u: f64, v: f64) -> HitRecord<'a> {
         let normal = if front_face { interpolated_normal } else { -interpolated_normal };
+        let tangent = Vec3(0.0, 0.0, 0.0);
+        let bitangent = Vec3(0.0, 0.0, 0.0);
         
-        HitRecord { p: p, normal: normal, t: t, front_face: front_face, material, u, v }
+        HitRecord { p: p, normal: normal, t: t, front_face: front_face, material, u, v, tangent, bitangent, vertex_color: None }
+    }
+
+    /// Sets the derivatives of the hit point with respect to `u` and `v`, which a HitRecord is constructed without
+    pub fn with_tangents(self, tangent: Vec3, bitangent: Vec3) -> Self {
+        HitRecord { tangent, bitangent, ..self }
+    }
+
+    /// `tangent` and `bitangent`, or if the hittable didn't set them, an arbitrary frame around the normal.
+    /// Only built when it's needed, since most hits are never normal or bump mapped
+    pub fn tangent_frame(&self) -> (Vec3, Vec3) {
+        if self.tangent == Vec3(0.0, 0.0, 0.0) && self.bitangent == Vec3(0.0, 0.0, 0.0) {
+            let Onb { u, v, .. } = Onb::build_from_w(self.normal);
+            (u, v)
+        }
+        else {
+            (self.tangent, self.bitangent)
+        }
+    }
+
+    /// Attaches a color interpolated from the vertices of a mesh
+    pub fn with_vertex_color(self, vertex_color: Color) -> Self {
+        HitRecord { vertex_color: Some(vertex_color), ..self }
     }
 }
\ No newline at end of file
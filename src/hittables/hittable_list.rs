@@ -1,8 +1,14 @@
 use std::fmt::Debug;
 
+use rand::Rng;
+
 use crate::types::{
     ray::Ray, 
-    aabb::AABB
+    aabb::AABB, 
+    vec3::{
+        Point, 
+        Vec3
+    }
 };
 use super::hittable::{
     Hit, 
@@ -30,6 +36,12 @@ macro_rules! hittable_list {
     }
 }
 
+impl Default for HittableList {
+    fn default() -> Self {
+        HittableList::new()
+    }
+}
+
 impl HittableList {
     /// Creates a empty HittableList
     pub fn new() -> HittableList {
@@ -46,6 +58,11 @@ impl HittableList {
     pub fn add(&mut self, x: Box<dyn Hit>) {
         self.objects.push(x);
     }
+
+    /// Returns true if this HittableList has no objects in it
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
 }
 
 impl Hit for HittableList {
@@ -84,4 +101,19 @@ impl Hit for HittableList {
         }
         Some(aabb)
     }
+
+    /// Objects are picked with equal probability, so the density is the average of 
+    /// the densities of every object
+    fn pdf_value(&self, origin: Point, direction: Vec3) -> f64 {
+        if self.objects.is_empty() {
+            return 0.0;
+        }
+        let weight = 1.0 / self.objects.len() as f64;
+        self.objects.iter().map(|object| weight * object.pdf_value(origin, direction)).sum()
+    }
+
+    fn random(&self, origin: Point) -> Vec3 {
+        let index = rand::thread_rng().gen_range(0..self.objects.len());
+        self.objects[index].random(origin)
+    }
 }
\ No newline at end of file
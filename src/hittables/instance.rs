@@ -1,11 +1,18 @@
+use std::sync::Arc;
+
 use crate::types::{
+    materials::Material, 
     transform::{
         TransformData, 
+        AnimatedTransform, 
         Transform, 
         InverseTransform
     }, 
     aabb::AABB, 
-    vec3::Vec3, 
+    vec3::{
+        Vec3, 
+        Point
+    }, 
     ray::Ray
 };
 
@@ -15,11 +22,23 @@ use super::hittable::{
 };
 
 /// Represents an "instance" of a different hittable - in other words, that other hittable
-/// but rotated / translated. Scaling not supported (yet!)
-/// TODO: implement scaling
+/// but moved by an affine transform (translation, rotation, scaling, shear)
+/// # Fields
+/// `object` - shared between instances, so that e.g a mesh (along with its BVH) placed many times is only loaded once.
+/// Putting instances in a `BVH` gives a two-level hierarchy, with the instances on top and the shared objects' own
+/// acceleration structures below
+///
+/// `material` - if set, replaces the material of everything hit on the object
 pub struct Instance {
-    transform: TransformData,
-    object: Box<dyn Hit>
+    transform: InstanceTransform,
+    object: Arc<dyn Hit>,
+    material: Option<Material>
+}
+
+/// Where an instance is placed - either fixed, or moving over time for motion blur
+pub enum InstanceTransform {
+    Static(Box<TransformData>),
+    Animated(AnimatedTransform)
 }
 
 /// Simple macros to compute the max and min of multiple things 
@@ -52,28 +71,35 @@ impl Hit for Instance {
     /// first transforms the ray into the coordinate system of the original object,
     /// then transforms the point and normal from the coordinate system of the original object
     /// back into world coordinates.
+    /// The transformed ray's direction isn't normalized, so `t` is the same in both coordinate systems
     fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let transformed_ray = r.inverse_transform(self.transform);
-        if let Some(hitrecord) = self.object.hit(transformed_ray, t_min, t_max) {
-            let transformed_p = hitrecord.p.transform(self.transform);
-            let transformed_normal = (hitrecord.p + hitrecord.normal).transform(self.transform) - transformed_p;
-            Some(HitRecord::construct(
-                transformed_p,
-                transformed_normal,
-                hitrecord.t,
-                r,
-                hitrecord.material,
-                hitrecord.u,
-                hitrecord.v
-            ))
-        }
-        else {
-            None
-        }
+        let transform = match &self.transform {
+            InstanceTransform::Static(transform) => **transform,
+            InstanceTransform::Animated(transform) => transform.at(r.time)
+        };
+        let transformed_ray = r.inverse_transform(transform);
+        let hitrecord = self.object.hit(transformed_ray, t_min, t_max)?;
+        // the normal already points against the ray, which the inverse transpose preserves,
+        // so `front_face` carries over as is
+        Some(HitRecord {
+            p: hitrecord.p.transform(transform),
+            normal: Vec3::normalized(transform.normal(hitrecord.normal)),
+            material: self.material.as_ref().unwrap_or(hitrecord.material),
+            tangent: transform.vector(hitrecord.tangent),
+            bitangent: transform.vector(hitrecord.bitangent),
+            ..hitrecord
+        })
     }
     /// Calculates the bounding box of a transformed object
-    /// By checking all 8 corners of bounding box after they are transformed into world coordinates
+    /// By checking all 8 corners of bounding box after they are transformed into world coordinates.
+    /// Animated instances are bounded over their whole motion from t0 to t1
     fn bounding_box(&self, t0: f64, t1: f64) -> Option<AABB> {
+        let transform = match &self.transform {
+            InstanceTransform::Static(transform) => **transform,
+            InstanceTransform::Animated(transform) => {
+                return self.object.bounding_box(t0, t1).map(|aabb| transform.motion_bounds(aabb, t0, t1));
+            }
+        };
         if let Some(aabb) = self.object.bounding_box(t0, t1) {
             let a0 = aabb.minimum;
             let a1 = Vec3(aabb.maximum.x(), aabb.minimum.y(), aabb.minimum.z());
@@ -83,14 +109,14 @@ impl Hit for Instance {
             let a5 = Vec3(aabb.maximum.x(), aabb.maximum.y(), aabb.minimum.z());
             let a6 = aabb.maximum;
             let a7 = Vec3(aabb.minimum.x(), aabb.maximum.y(), aabb.maximum.z());
-            let a0 = a0.transform(self.transform);
-            let a1 = a1.transform(self.transform);
-            let a2 = a2.transform(self.transform);
-            let a3 = a3.transform(self.transform);
-            let a4 = a4.transform(self.transform);
-            let a5 = a5.transform(self.transform);
-            let a6 = a6.transform(self.transform);
-            let a7 = a7.transform(self.transform);
+            let a0 = a0.transform(transform);
+            let a1 = a1.transform(transform);
+            let a2 = a2.transform(transform);
+            let a3 = a3.transform(transform);
+            let a4 = a4.transform(transform);
+            let a5 = a5.transform(transform);
+            let a6 = a6.transform(transform);
+            let a7 = a7.transform(transform);
             let min = Vec3(
                 min!(a0.0, a1.0, a2.0, a3.0, a4.0, a5.0, a6.0, a7.0),
                 min!(a0.1, a1.1, a2.1, a3.1, a4.1, a5.1, a6.1, a7.1),
@@ -107,11 +133,56 @@ impl Hit for Instance {
             None
         }
     }
+
+    /// Computes the density in the coordinate system of the original object, then converts it to world
+    /// coordinates. Scaling and shear don't preserve solid angle, so the density is multiplied by
+    /// the Jacobian of the mapping from world directions to object directions, `|det A| / |A w|^3`
+    /// for the inverse linear transform `A` and unit direction `w`
+    fn pdf_value(&self, origin: Point, direction: Vec3) -> f64 {
+        let transform = self.sampling_transform();
+        let direction = Vec3::normalized(direction);
+        let transformed_origin = origin.inverse_transform(transform);
+        let transformed_direction = transform.inverse_vector(direction);
+        let length = transformed_direction.length();
+        let jacobian = transform.inverse_determinant().abs() / (length * length * length);
+        self.object.pdf_value(transformed_origin, transformed_direction) * jacobian
+    }
+
+    fn random(&self, origin: Point) -> Vec3 {
+        let transform = self.sampling_transform();
+        let transformed_origin = origin.inverse_transform(transform);
+        let direction = self.object.random(transformed_origin);
+        (transformed_origin + direction).transform(transform) - origin
+    }
 }
 
 impl Instance {
     /// Create a new Instance from a existing Hittable and a Transform 
     pub fn new(object: Box<dyn Hit>, transform: TransformData) -> Instance {
-        Instance { transform, object }
+        Instance::shared(Arc::from(object), transform)
+    }
+
+    /// Create a new Instance of a Hittable which other instances may also refer to
+    pub fn shared(object: Arc<dyn Hit>, transform: TransformData) -> Instance {
+        Instance { transform: InstanceTransform::Static(Box::new(transform)), object, material: None }
+    }
+
+    /// Create a new Instance which moves along with `transform` over time
+    pub fn animated(object: Arc<dyn Hit>, transform: AnimatedTransform) -> Instance {
+        Instance { transform: InstanceTransform::Animated(transform), object, material: None }
+    }
+
+    /// Transform used when sampling the instance as a light. Sampling doesn't know the time of the ray,
+    /// so animated instances are sampled where they are at their first keyframe
+    fn sampling_transform(&self) -> TransformData {
+        match &self.transform {
+            InstanceTransform::Static(transform) => **transform,
+            InstanceTransform::Animated(transform) => transform.start()
+        }
+    }
+
+    /// Shades the instance with `material` instead of the object's own materials
+    pub fn with_material(self, material: Material) -> Instance {
+        Instance { material: Some(material), ..self }
     }
 }
\ No newline at end of file
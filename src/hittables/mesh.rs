@@ -1,9 +1,13 @@
-use std::{path::Path, sync::Arc};
+use std::{collections::HashMap, fmt::Display, path::Path, sync::Arc};
 
+use image::ImageError;
 use obj::{
     Obj, 
     ObjError, 
-    MtlLibsLoadError
+    ObjMaterial, 
+    MtlLibsLoadError, 
+    IndexTuple, 
+    Material as MtlMaterial
 };
 
 use crate::types::{
@@ -14,7 +18,22 @@ use crate::types::{
     }, 
     ray::Ray, 
     aabb::AABB, 
-    materials::Material, texture::SolidColor
+    bvh::{
+        LinearBVH, 
+        SplitStrategy
+    }, 
+    materials::{
+        Material, 
+        MixFactor, 
+        principled::PrincipledBsdf
+    }, 
+    texture::{
+        Texture, 
+        SolidColor, 
+        ImageTexture, 
+        VertexColorTexture
+    }, 
+    color::Color
 };
 
 use super::{
@@ -22,9 +41,16 @@ use super::{
         Hit, 
         HitRecord
     }, 
-    tri::Triangle
+    tri::Triangle, 
+    ply::{
+        Ply, 
+        PlyError
+    }
 };
 
+/// Default `smoothing_angle` of `Mesh::from_file_with_smoothing`, in degrees
+pub const DEFAULT_SMOOTHING_ANGLE: f64 = 30.0;
+
 /// Enum representing whether a triangle should be shaded flat or 
 /// smooth. If smooth, need each vertex normal separately so they can be interpolated
 pub enum VertexNormals {
@@ -34,8 +60,6 @@ pub enum VertexNormals {
 
 /// Simple 3D mesh, containing vertex positions, vertex normals,
 /// triangles (along with corresponding vertex normals), and texture coordinates
-/// Note: use with Blender's .obj exportter requires that mesh be triangulated,
-/// and that the vertex data be included also
 pub struct MeshTri {
     pub vertex_indices: [usize; 3],
     pub vertex_normals: VertexNormals,
@@ -43,20 +67,51 @@ pub struct MeshTri {
     pub material_index: usize
 }
 
+/// `triangles` are stored in the order of the leaves of `bvh`, which is built over them when
+/// the mesh is loaded. `vertex_colors`, if the file has them, line up with `vertices`
 pub struct Mesh {
     pub triangles: Vec<MeshTri>,
     pub vertices: Vec<Vec3>,
     pub bounding_box: AABB,
-    pub materials: Vec<Material>
+    pub materials: Vec<Material>,
+    pub vertex_colors: Option<Vec<Color>>,
+    bvh: LinearBVH
 }
 
 #[derive(Debug)]
 pub enum MeshError {
     MeshLoadError(ObjError),
     MaterialLoadError(MtlLibsLoadError),
-    UntriangulatedError,
+    /// A texture referred to by a material couldn't be loaded. Holds the texture's path as written in the .mtl file
+    TextureLoadError(String, ImageError),
+    /// A face refers to a position, texture coordinate or normal past the end of the ones in the file
+    IndexOutOfRange {
+        kind: &'static str,
+        index: usize
+    },
+    /// The file doesn't have any faces
+    Empty,
+    PlyLoadError(PlyError),
+}
+
+impl Display for MeshError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MeshError::MeshLoadError(error) => write!(f, "{}", error),
+            MeshError::MaterialLoadError(error) => write!(f, "{}", error),
+            MeshError::TextureLoadError(path, error) => write!(f, "unable to load texture '{}': {}", path, error),
+            MeshError::IndexOutOfRange { kind, index } => {
+                // indices in the file start at 1
+                write!(f, "face refers to {} {}, which doesn't exist", kind, index + 1)
+            },
+            MeshError::Empty => write!(f, "no faces"),
+            MeshError::PlyLoadError(error) => write!(f, "{}", error)
+        }
+    }
 }
 
+impl std::error::Error for MeshError {}
+
 impl From<ObjError> for MeshError {
     fn from(x: ObjError) -> Self {
         MeshError::MeshLoadError(x)
@@ -69,38 +124,131 @@ impl From<MtlLibsLoadError> for MeshError {
     }
 }
 
+impl From<PlyError> for MeshError {
+    fn from(x: PlyError) -> Self {
+        MeshError::PlyLoadError(x)
+    }
+}
+
 impl Mesh {
+    /// Loads a mesh from a .obj file (along with the materials from its .mtl files) or a .ply file. Faces without
+    /// normals are given smooth normals, see `from_file_with_smoothing`
     pub fn from_file(filepath: &Path) -> Result<Mesh, MeshError> {
+        Mesh::from_file_with_smoothing(filepath, DEFAULT_SMOOTHING_ANGLE)
+    }
+
+    /// Loads a mesh from a .obj or .ply file (chosen by its extension). Polygons are triangulated, and faces which 
+    /// don't have normals in the file get generated ones: each corner's normal averages the faces around that vertex 
+    /// which are within `smoothing_angle` (in degrees) of the corner's own face, so 0 gives flat shading.
+    /// Faces without texture coordinates use the barycentric coordinates of each triangle instead
+    pub fn from_file_with_smoothing(filepath: &Path, smoothing_angle: f64) -> Result<Mesh, MeshError> {
+        let is_ply = filepath.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("ply"));
+        if is_ply {
+            return Mesh::from_ply(filepath, smoothing_angle);
+        }
+
         let mut obj = Obj::load(filepath)?;
         obj.load_mtls()?;
 
         let obj_data = &obj.data;
+        let directory = filepath.parent().unwrap_or(Path::new(""));
+
+        let vertices = obj_data.position.iter()
+            .map(|chunk| Vec3(chunk[0] as f64, chunk[1] as f64, chunk[2] as f64))
+            .collect();
+        let normals = obj_data.normal.iter()
+            .map(|chunk| Vec3(chunk[0] as f64, chunk[1] as f64, chunk[2] as f64))
+            .collect();
+        let uv_coords = obj_data.texture.iter()
+            .map(|chunk| UV(Vec2(chunk[0] as f64, chunk[1] as f64)))
+            .collect();
+
+        let mut polygons = Vec::new();
+        // groups using the same material share one copy of it, keyed by the material's name.
+        // Groups without a material, or with one missing from the .mtl files, are keyed by None
+        let mut materials = Vec::new();
+        let mut material_indices: HashMap<Option<String>, usize> = HashMap::new();
 
-        if obj_data.objects.len() > 1 {
-            eprintln!("warning: more than 1 model in .obj file");
+        for group in obj_data.objects.iter().flat_map(|object| object.groups.iter()) {
+            let key = match &group.material {
+                Some(ObjMaterial::Mtl(material)) => Some(material.name.clone()),
+                Some(ObjMaterial::Ref(_)) | None => None
+            };
+            let i = match material_indices.get(&key) {
+                Some(&i) => i,
+                None => {
+                    let material = match &group.material {
+                        Some(ObjMaterial::Mtl(material)) => Mesh::material_from_mtl(material, directory)?,
+                        Some(ObjMaterial::Ref(_)) | None => Material::Lambertian { 
+                            albedo: Arc::new(SolidColor::from(Vec3(0.8, 0.8, 0.8)))
+                        }
+                    };
+                    materials.push(material);
+                    material_indices.insert(key, materials.len() - 1);
+                    materials.len() - 1
+                }
+            };
+            polygons.extend(group.polys.iter().map(|poly| (poly.0.clone(), i)));
         }
 
-        let mut vertices = Vec::new();
-        let mut normals = Vec::new();
-        let mut uv_coords = Vec::new();
+        Mesh::from_polygons(vertices, normals, uv_coords, None, polygons, materials, smoothing_angle)
+    }
+
+    /// Loads a mesh from a .ply file. PLY files don't have materials, so meshes with vertex colors are shaded
+    /// with a `VertexColorTexture`, and everything else is plain grey
+    fn from_ply(filepath: &Path, smoothing_angle: f64) -> Result<Mesh, MeshError> {
+        let ply = Ply::load(filepath)?;
 
+        let albedo: Arc<dyn Texture> = match ply.colors {
+            Some(_) => Arc::new(VertexColorTexture::new(Vec3(0.8, 0.8, 0.8))),
+            None => Arc::new(SolidColor::from(Vec3(0.8, 0.8, 0.8)))
+        };
+        // every attribute is stored per vertex, so they share the position's index
+        let has_uvs = ply.texture_coordinates.is_some();
+        let has_normals = ply.normals.is_some();
+        let polygons = ply.faces.into_iter().map(|face| {
+            let polygon = face.into_iter()
+                .map(|index| IndexTuple(index, has_uvs.then_some(index), has_normals.then_some(index)))
+                .collect();
+            (polygon, 0)
+        }).collect();
+
+        Mesh::from_polygons(
+            ply.positions,
+            ply.normals.unwrap_or_default(),
+            ply.texture_coordinates.unwrap_or_default(),
+            ply.colors,
+            polygons,
+            vec![Material::Lambertian { albedo }],
+            smoothing_angle
+        )
+    }
+
+    /// Builds a mesh from polygons, each a list of corners indexing into `vertices`, `normals` and `uv_coords`
+    /// along with the index of its material in `materials`. `vertex_colors`, if there are any, are indexed 
+    /// the same way as `vertices`
+    pub(crate) fn from_polygons(
+        vertices: Vec<Vec3>, 
+        normals: Vec<Vec3>, 
+        uv_coords: Vec<UV>, 
+        vertex_colors: Option<Vec<Color>>, 
+        polygons: Vec<(Vec<IndexTuple>, usize)>, 
+        materials: Vec<Material>, 
+        smoothing_angle: f64
+    ) -> Result<Mesh, MeshError> {
         let mut x_min = f64::INFINITY;
         let mut y_min = f64::INFINITY;
         let mut z_min = f64::INFINITY;
         let mut x_max = f64::NEG_INFINITY;
         let mut y_max = f64::NEG_INFINITY;
         let mut z_max = f64::NEG_INFINITY;
-        for chunk in obj_data.position.iter() {
-            let x = chunk[0] as f64;
-            let y = chunk[1] as f64;
-            let z = chunk[2] as f64;
+        for &Vec3(x, y, z) in vertices.iter() {
             x_max = f64::max(x, x_max);
             y_max = f64::max(y, y_max);
             z_max = f64::max(z, z_max);
             x_min = f64::min(x, x_min);
             y_min = f64::min(y, y_min);
             z_min = f64::min(z, z_min);
-            vertices.push(Vec3(x, y, z));
         }
 
         let bounding_box = AABB::new(
@@ -108,134 +256,409 @@ impl Mesh {
             Vec3(x_max, y_max, z_max)
         );
 
-        for chunk in obj_data.normal.iter() {
-            normals.push(Vec3(chunk[0] as f64, chunk[1] as f64, chunk[2] as f64));
+        let mut corners = Vec::new();
+        for (polygon, material_index) in polygons {
+            for tuple in polygon.iter() {
+                if tuple.0 >= vertices.len() {
+                    return Err(MeshError::IndexOutOfRange { kind: "position", index: tuple.0 });
+                }
+                match (tuple.1, tuple.2) {
+                    (Some(index), _) if index >= uv_coords.len() => {
+                        return Err(MeshError::IndexOutOfRange { kind: "texture coordinate", index });
+                    },
+                    (_, Some(index)) if index >= normals.len() => {
+                        return Err(MeshError::IndexOutOfRange { kind: "normal", index });
+                    },
+                    _ => {}
+                }
+            }
+
+            let points: Vec<Vec3> = polygon.iter().map(|tuple| vertices[tuple.0]).collect();
+            for [a, b, c] in triangulate(&points) {
+                corners.push(([polygon[a], polygon[b], polygon[c]], material_index));
+            }
         }
-        
-        for chunk in obj_data.texture.iter() {
-            uv_coords.push(UV(Vec2(chunk[0] as f64, chunk[1] as f64)));
+
+        if corners.is_empty() {
+            return Err(MeshError::Empty);
         }
 
+        let generated_normals = generate_normals(&vertices, &corners, smoothing_angle);
         let mut tris = Vec::new();
-        let object = &obj_data.objects[0];
-        let mut materials = Vec::new();
+        for (([v0, v1, v2], material_index), generated) in corners.iter().zip(generated_normals) {
+            let face_normal = Vec3::normalized(Vec3::cross(vertices[v1.0] - vertices[v0.0], vertices[v2.0] - vertices[v0.0]));
+            let [n0, n1, n2] = match (v0.2, v1.2, v2.2) {
+                (Some(n0), Some(n1), Some(n2)) => [normals[n0], normals[n1], normals[n2]],
+                _ => generated
+            };
 
-        for (i, group) in object.groups.iter().enumerate() {
-            let material = match &group.material {
-                None => {
-                    Material::Lambertian { 
-                        albedo: Arc::new(SolidColor::from(Vec3(1.0, 0.0, 0.75)))
-                    }
-                }
-                Some(_) => {
-                    Material::Lambertian { 
-                        albedo: Arc::new(SolidColor::from(Vec3(1.0, 0.0, 0.75))) 
-                    }
-                },
+            let vertex_normals = if n0 == n1 && n1 == n2 {
+                VertexNormals::Flat(n0)
+            } 
+            else {
+                VertexNormals::Smooth([n0, n1, n2, face_normal]) 
             };
-            materials.push(material);
-            for poly in group.polys.iter() {
-                let index_tup_vec = &poly.0;
-                if index_tup_vec.len() != 3 {
-                    return Err(MeshError::UntriangulatedError);
-                }
 
-                let v0 = index_tup_vec[0];
-                let v1 = index_tup_vec[1];
-                let v2 = index_tup_vec[2];
-
-                let n0 = normals[v0.2.unwrap()];
-                let n1 = normals[v1.2.unwrap()];
-                let n2 = normals[v2.2.unwrap()];
-
-                let vertex_normals = if n0 == n1 && n1 == n2 {
-                    VertexNormals::Flat(n0)
-                } 
-                else {
-                    let v0 = vertices[v0.0];
-                    let v1 = vertices[v1.0];
-                    let v2 = vertices[v2.0];
-                    let face_normal = Vec3::normalized(Vec3::cross(v1 - v0, v2 - v0));
-                    VertexNormals::Smooth([n0, n1, n2, face_normal]) 
-                };
-
-                let t0 = uv_coords[v0.1.unwrap()];
-                let t1 = uv_coords[v1.1.unwrap()];
-                let t2 = uv_coords[v2.1.unwrap()];
-
-                let triangle = MeshTri {
-                    vertex_indices: [v0.0, v1.0, v2.0],
-                    vertex_normals,
-                    texture_coordinates: [t0, t1, t2],
-                    material_index: i,
-                };
-
-                tris.push(triangle);
-            }
+            // without texture coordinates, fall back to the barycentric coordinates of the triangle
+            let texture_coordinates = match (v0.1, v1.1, v2.1) {
+                (Some(t0), Some(t1), Some(t2)) => [uv_coords[t0], uv_coords[t1], uv_coords[t2]],
+                _ => [UV(Vec2(0.0, 0.0)), UV(Vec2(1.0, 0.0)), UV(Vec2(0.0, 1.0))]
+            };
+
+            tris.push(MeshTri {
+                vertex_indices: [v0.0, v1.0, v2.0],
+                vertex_normals,
+                texture_coordinates,
+                material_index: *material_index,
+            });
         }
+        let (bvh, triangles) = Mesh::build_bvh(&vertices, tris);
         Ok(Mesh {
             vertices,
-            triangles: tris,
+            triangles,
             bounding_box,
-            materials
+            materials,
+            vertex_colors,
+            bvh
         })
     }
+
+    /// Converts a material from a .mtl file. Emissive materials (with a nonzero `Ke`) become lights, and everything
+    /// else a `PrincipledBsdf`:
+    /// `Kd` / `map_Kd` - base color, with texture paths relative to `directory`
+    ///
+    /// `Ks` - strength of specular reflection
+    ///
+    /// `Ns` - specular exponent, from 0 (rough) to 1000 (smooth), converted to roughness the way Blender exports it
+    ///
+    /// `Ni` - index of refraction
+    ///
+    /// `illum` - illumination models 4, 6 and 7 are transmissive (like glass), others are opaque
+    ///
+    /// `Tf` - transmission filter, how much light is transmitted if `illum` is transmissive or not given.
+    /// Transmissive models without one transmit everything
+    ///
+    /// `d` (or `Tr`, which is `1 - d`) / `map_d` - dissolve, how much of the surface is there. Anything less than 1
+    /// is cut out, mixing in a `Material::Transparent` which rays pass straight through. `map_d` replaces `d`
+    fn material_from_mtl(material: &MtlMaterial, directory: &Path) -> Result<Material, MeshError> {
+        let color = |value: [f32; 3]| Vec3(value[0] as f64, value[1] as f64, value[2] as f64);
+        let constant = |x: f64| -> Arc<dyn Texture> { Arc::new(SolidColor::from(Vec3(x, x, x))) };
+
+        if let Some(emit) = material.ke.map(color) {
+            if emit.0 > 0.0 || emit.1 > 0.0 || emit.2 > 0.0 {
+                return Ok(Material::DiffuseLight { emit: Arc::new(SolidColor::from(emit)) });
+            }
+        }
+
+        let base_color: Arc<dyn Texture> = match &material.map_kd {
+            Some(path) => {
+                let texture = ImageTexture::open(&directory.join(path))
+                    .map_err(|error| MeshError::TextureLoadError(path.clone(), error))?;
+                Arc::new(texture)
+            },
+            None => Arc::new(SolidColor::from(material.kd.map_or(Vec3(0.8, 0.8, 0.8), color)))
+        };
+
+        let mut bsdf = PrincipledBsdf::new(base_color);
+        if let Some(specular) = material.ks.map(color) {
+            bsdf.specular = constant(((specular.0 + specular.1 + specular.2) / 3.0).clamp(0.0, 1.0));
+        }
+        if let Some(exponent) = material.ns {
+            bsdf.roughness = constant(1.0 - (exponent as f64 / 1000.0).clamp(0.0, 1.0).sqrt());
+        }
+        if let Some(index_of_refraction) = material.ni {
+            // 0 is sometimes written to mean "not set"
+            if index_of_refraction >= 1.0 {
+                bsdf.index_of_refraction = index_of_refraction as f64;
+            }
+        }
+        let filter = material.tf.map(color).map(|tf| ((tf.0 + tf.1 + tf.2) / 3.0).clamp(0.0, 1.0));
+        let transmission = match (material.illum, filter) {
+            (Some(4 | 6 | 7), filter) => filter.unwrap_or(1.0),
+            (None, Some(filter)) => filter,
+            _ => 0.0
+        };
+        bsdf.transmission = constant(transmission);
+        let surface = Material::Principled(Box::new(bsdf));
+
+        let opacity = match (&material.map_d, material.d, material.tr) {
+            (Some(path), _, _) => {
+                let texture = ImageTexture::open(&directory.join(path))
+                    .map_err(|error| MeshError::TextureLoadError(path.clone(), error))?;
+                MixFactor::Texture(Arc::new(texture))
+            },
+            (None, Some(d), _) if d < 1.0 => MixFactor::Constant(d.max(0.0) as f64),
+            (None, None, Some(tr)) if tr > 0.0 => MixFactor::Constant((1.0 - tr).max(0.0) as f64),
+            _ => return Ok(surface)
+        };
+        Ok(Material::Mix { first: Box::new(Material::Transparent), second: Box::new(surface), factor: opacity })
+    }
+
+    /// Builds a BVH over `triangles`, and returns it along with the triangles reordered to match its leaves
+    fn build_bvh(vertices: &[Vec3], triangles: Vec<MeshTri>) -> (LinearBVH, Vec<MeshTri>) {
+        let bounds: Vec<AABB> = triangles.iter().map(|tri| {
+            let [a, b, c] = tri.vertex_indices.map(|index| vertices[index]);
+            Triangle::bounds(a, b, c)
+        }).collect();
+        let (bvh, order) = LinearBVH::build(&bounds, SplitStrategy::Sah);
+
+        let mut triangles: Vec<Option<MeshTri>> = triangles.into_iter().map(Some).collect();
+        let triangles = order.into_iter()
+            .map(|index| triangles[index].take().expect("every triangle appears once in the BVH"))
+            .collect();
+        (bvh, triangles)
+    }
 }
 
-impl Hit for Mesh {
-    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        // Meshes are quite expensive, so check if ray hits bounding box first
-        let bounding_box = self.bounding_box(t_min, t_max).unwrap();
-        if bounding_box.hit(r, t_min, t_max) {
-            let mut t_max = t_max;
-            let mut closest_so_far = None;
-            for tri in &self.triangles {
-                let v0 = self.vertices[tri.vertex_indices[0]];
-                let v1 = self.vertices[tri.vertex_indices[1]];
-                let v2 = self.vertices[tri.vertex_indices[2]];
-
-                if let Some((t, u, v)) = Triangle::moller_trumbore(v0, v1, v2, r, t_min, t_max) {
-                    if t < t_max {
-                        t_max = t;
-
-                        let front_face;
-                        let normal;
-                        match tri.vertex_normals {
-                            VertexNormals::Flat(face_normal) => {
-                                front_face = Vec3::dot(face_normal, r.direction) < 0.0;
-                                normal = face_normal;
-                            },
-                            VertexNormals::Smooth(vertex_normals) => {
-                                #[cfg(feature="ray_debug")]
-                                {
-                                    println!("{:?}", vertex_normals);
-                                }
-                                let w = 1.0 - u - v;
-                                front_face = Vec3::dot(vertex_normals[3], r.direction) < 0.0;
-                                normal = Vec3::normalized(w * vertex_normals[0] + u * vertex_normals[1] + v * vertex_normals[2]);
-                            },
-                        };
-
-                        closest_so_far = Some(
-                            HitRecord::construct_from_interpolated_normal(
-                                r.at(t), 
-                                normal,
-                                front_face,
-                                t, 
-                                r, 
-                                &self.materials[tri.material_index], 
-                                u, 
-                                v
-                            )
-                        )
-                    }
+impl Mesh {
+    /// Derivatives of a point on `tri` with respect to its texture coordinates, found by solving 
+    /// for the (u, v) directions along the triangle's edges. Falls back to the edges themselves 
+    /// when the texture coordinates are degenerate
+    fn tangents(&self, tri: &MeshTri) -> (Vec3, Vec3) {
+        let p0 = self.vertices[tri.vertex_indices[0]];
+        let e1 = self.vertices[tri.vertex_indices[1]] - p0;
+        let e2 = self.vertices[tri.vertex_indices[2]] - p0;
+
+        let [UV(Vec2(u0, v0)), UV(Vec2(u1, v1)), UV(Vec2(u2, v2))] = tri.texture_coordinates;
+        let (du1, dv1) = (u1 - u0, v1 - v0);
+        let (du2, dv2) = (u2 - u0, v2 - v0);
+        let determinant = du1 * dv2 - du2 * dv1;
+        if determinant.abs() < 1e-12 {
+            return (e1, e2);
+        }
+
+        let dpdu = (dv2 * e1 - dv1 * e2) / determinant;
+        let dpdv = (du1 * e2 - du2 * e1) / determinant;
+        (dpdu, dpdv)
+    }
+
+    fn hit_triangle(&self, tri: &MeshTri, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let v0 = self.vertices[tri.vertex_indices[0]];
+        let v1 = self.vertices[tri.vertex_indices[1]];
+        let v2 = self.vertices[tri.vertex_indices[2]];
+
+        let (t, u, v) = Triangle::moller_trumbore(v0, v1, v2, r, t_min, t_max)?;
+
+        let front_face;
+        let normal;
+        match tri.vertex_normals {
+            VertexNormals::Flat(face_normal) => {
+                front_face = Vec3::dot(face_normal, r.direction) < 0.0;
+                normal = face_normal;
+            },
+            VertexNormals::Smooth(vertex_normals) => {
+                #[cfg(feature="ray_debug")]
+                {
+                    println!("{:?}", vertex_normals);
+                }
+                let w = 1.0 - u - v;
+                front_face = Vec3::dot(vertex_normals[3], r.direction) < 0.0;
+                normal = Vec3::normalized(w * vertex_normals[0] + u * vertex_normals[1] + v * vertex_normals[2]);
+            },
+        };
+
+        let [UV(Vec2(u0, v0)), UV(Vec2(u1, v1)), UV(Vec2(u2, v2))] = tri.texture_coordinates;
+        let w = 1.0 - u - v;
+        let (tangent, bitangent) = self.tangents(tri);
+
+        let record = HitRecord::construct_from_interpolated_normal(
+            r.at(t),
+            normal,
+            front_face,
+            t,
+            r,
+            &self.materials[tri.material_index],
+            w * u0 + u * u1 + v * u2,
+            w * v0 + u * v1 + v * v2
+        ).with_tangents(tangent, bitangent);
+
+        match &self.vertex_colors {
+            Some(colors) => {
+                let [c0, c1, c2] = tri.vertex_indices.map(|index| colors[index]);
+                Some(record.with_vertex_color(w * c0 + u * c1 + v * c2))
+            },
+            None => Some(record)
+        }
+    }
+}
+
+/// Splits a polygon into triangles by ear clipping, and returns the indices of their corners in `points`.
+/// The polygon is projected onto the plane it (roughly) lies in, then triangles are repeatedly cut off at
+/// convex corners which don't have any other corner inside them. If that gets stuck (e.g the polygon
+/// intersects itself), the rest of it is split into a fan instead
+fn triangulate(points: &[Vec3]) -> Vec<[usize; 3]> {
+    if points.len() < 3 {
+        return Vec::new();
+    }
+    if points.len() == 3 {
+        return vec![[0, 1, 2]];
+    }
+
+    // Newell's method, which is robust to non-planar and concave polygons
+    let mut normal = Vec3(0.0, 0.0, 0.0);
+    for (i, current) in points.iter().enumerate() {
+        let next = points[(i + 1) % points.len()];
+        normal += Vec3(
+            (current.1 - next.1) * (current.2 + next.2),
+            (current.2 - next.2) * (current.0 + next.0),
+            (current.0 - next.0) * (current.1 + next.1)
+        );
+    }
+    let axis = if normal.0.abs() > normal.1.abs() && normal.0.abs() > normal.2.abs() { 0 } 
+        else if normal.1.abs() > normal.2.abs() { 1 } 
+        else { 2 };
+    let (a, b) = [(1, 2), (2, 0), (0, 1)][axis];
+    // positive if the polygon winds counter-clockwise in the plane it's projected onto
+    let orientation = if normal[axis] < 0.0 { -1.0 } else { 1.0 };
+    let projected: Vec<(f64, f64)> = points.iter().map(|point| (point[a], point[b])).collect();
+    let cross = |o: usize, p: usize, q: usize| {
+        let (o, p, q) = (projected[o], projected[p], projected[q]);
+        orientation * ((p.0 - o.0) * (q.1 - o.1) - (p.1 - o.1) * (q.0 - o.0))
+    };
+
+    let mut remaining: Vec<usize> = (0..points.len()).collect();
+    let mut triangles = Vec::with_capacity(points.len() - 2);
+    while remaining.len() > 3 {
+        let count = remaining.len();
+        let ear = (0..count).find(|&i| {
+            let (prev, current, next) = (remaining[(i + count - 1) % count], remaining[i], remaining[(i + 1) % count]);
+            cross(prev, current, next) > 0.0 && remaining.iter().all(|&other| {
+                other == prev || other == current || other == next
+                    || cross(prev, current, other) < 0.0 || cross(current, next, other) < 0.0 || cross(next, prev, other) < 0.0
+            })
+        });
+        match ear {
+            Some(i) => {
+                triangles.push([remaining[(i + count - 1) % count], remaining[i], remaining[(i + 1) % count]]);
+                remaining.remove(i);
+            },
+            None => break
+        }
+    }
+    for i in 1..remaining.len() - 1 {
+        triangles.push([remaining[0], remaining[i], remaining[i + 1]]);
+    }
+    triangles
+}
+
+/// Smooth normals for the corners of each triangle, found by averaging the (area weighted) normals of the triangles
+/// around each vertex which face within `smoothing_angle` degrees of the corner's own triangle
+fn generate_normals(vertices: &[Vec3], triangles: &[([IndexTuple; 3], usize)], smoothing_angle: f64) -> Vec<[Vec3; 3]> {
+    let face_normals: Vec<Vec3> = triangles.iter().map(|([v0, v1, v2], _)| {
+        Vec3::cross(vertices[v1.0] - vertices[v0.0], vertices[v2.0] - vertices[v0.0])
+    }).collect();
+
+    let mut adjacent: Vec<Vec<usize>> = vec![Vec::new(); vertices.len()];
+    for (i, (corners, _)) in triangles.iter().enumerate() {
+        for corner in corners {
+            adjacent[corner.0].push(i);
+        }
+    }
+
+    let threshold = smoothing_angle.to_radians().cos();
+    // normals are only left as they are when they're exactly zero, since small meshes have small (but valid) normals
+    let unit = |normal: Vec3| if normal == Vec3(0.0, 0.0, 0.0) { normal } else { Vec3::normalized(normal) };
+    triangles.iter().enumerate().map(|(i, (corners, _))| {
+        let own = unit(face_normals[i]);
+        corners.map(|corner| {
+            let mut sum = Vec3(0.0, 0.0, 0.0);
+            for &other in &adjacent[corner.0] {
+                if other == i || Vec3::dot(own, unit(face_normals[other])) >= threshold {
+                    sum += face_normals[other];
                 }
             }
-            closest_so_far
-        } else { None }
+            if sum == Vec3(0.0, 0.0, 0.0) { own } else { Vec3::normalized(sum) }
+        })
+    }).collect()
+}
+
+impl Hit for Mesh {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.bvh.hit(r, t_min, t_max, |index, r, t_min, t_max| self.hit_triangle(&self.triangles[index], r, t_min, t_max))
     }
 
     fn bounding_box(&self, _: f64, _: f64) -> Option<AABB> {
         Some(self.bounding_box)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn corner(index: usize) -> IndexTuple {
+        IndexTuple(index, None, None)
+    }
+
+    /// A U shape, with a notch cut into its top edge that a fan from the first corner would cover
+    const NOTCHED_SQUARE: [(f64, f64); 8] = [(0.0, 0.0), (3.0, 0.0), (3.0, 2.0), (2.0, 2.0), (2.0, 1.0), (1.0, 1.0), (1.0, 2.0), (0.0, 2.0)];
+
+    fn signed_area(polygon: &[(f64, f64)]) -> f64 {
+        (0..polygon.len()).map(|i| {
+            let (p, q) = (polygon[i], polygon[(i + 1) % polygon.len()]);
+            p.0 * q.1 - q.0 * p.1
+        }).sum::<f64>() / 2.0
+    }
+
+    fn inside(polygon: &[(f64, f64)], point: (f64, f64)) -> bool {
+        let mut crossings = 0;
+        for i in 0..polygon.len() {
+            let (p, q) = (polygon[i], polygon[(i + 1) % polygon.len()]);
+            if (p.1 > point.1) != (q.1 > point.1) && point.0 < p.0 + (point.1 - p.1) / (q.1 - p.1) * (q.0 - p.0) {
+                crossings += 1;
+            }
+        }
+        crossings % 2 == 1
+    }
+
+    /// Triangulates `outline` placed in 3D by `embed`, and checks the triangles wind around `normal` like the
+    /// polygon does, stay inside it and cover all of it
+    fn check_triangulation(outline: &[(f64, f64)], embed: fn((f64, f64)) -> Vec3, normal: Vec3) {
+        let points: Vec<Vec3> = outline.iter().map(|&point| embed(point)).collect();
+        let triangles = triangulate(&points);
+        assert_eq!(triangles.len(), outline.len() - 2);
+
+        let mut area = 0.0;
+        for triangle in triangles {
+            let [p0, p1, p2] = triangle.map(|index| points[index]);
+            assert!(Vec3::dot(Vec3::cross(p1 - p0, p2 - p0), normal) > 0.0, "{:?} is wound backwards", triangle);
+            let corners = triangle.map(|index| outline[index]);
+            let centroid = ((corners[0].0 + corners[1].0 + corners[2].0) / 3.0, (corners[0].1 + corners[1].1 + corners[2].1) / 3.0);
+            assert!(inside(outline, centroid), "{:?} is outside the polygon", triangle);
+            area += signed_area(&corners).abs();
+        }
+        assert!((area - signed_area(outline).abs()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn triangulates_concave_polygons() {
+        check_triangulation(&NOTCHED_SQUARE, |(x, y)| Vec3(x, y, 0.0), Vec3(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn triangulates_clockwise_polygons_in_other_planes() {
+        let mut outline = NOTCHED_SQUARE.to_vec();
+        outline.reverse();
+        check_triangulation(&outline, |(y, z)| Vec3(0.5 * z, y, z), Vec3(-1.0, 0.0, 0.5));
+    }
+
+    #[test]
+    fn triangulates_triangles_and_less_as_is() {
+        let points = [Vec3(0.0, 0.0, 0.0), Vec3(1.0, 0.0, 0.0), Vec3(0.0, 1.0, 0.0)];
+        assert_eq!(triangulate(&points), vec![[0, 1, 2]]);
+        assert!(triangulate(&points[..2]).is_empty());
+    }
+
+    #[test]
+    fn generated_normals_of_tiny_triangles_are_normalized() {
+        // the face normals are around 1e-10 long, well under the tolerance of `Vec3::near_zero`
+        let vertices = vec![Vec3(0.0, 0.0, 0.0), Vec3(1e-5, 0.0, 0.0), Vec3(0.0, 1e-5, 0.0), Vec3(1e-5, 1e-5, 1e-6)];
+        let triangles = vec![([corner(0), corner(1), corner(2)], 0), ([corner(1), corner(3), corner(2)], 0)];
+        for normals in generate_normals(&vertices, &triangles, 60.0) {
+            for normal in normals {
+                assert!((normal.length() - 1.0).abs() < 1e-9, "{:?} isn't normalized", normal);
+                assert!(normal.z() > 0.9);
+            }
+        }
+    }
+}
@@ -7,4 +7,5 @@ pub mod block;
 pub mod instance;
 pub mod constant_medium;
 pub mod tri;
-pub mod mesh;
\ No newline at end of file
+pub mod mesh;
+pub mod ply;
\ No newline at end of file
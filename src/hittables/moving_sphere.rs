@@ -55,7 +55,9 @@ impl Hit for MovingSphere {
     fn hit(&self, ray: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
         if let Some((root, outward_normal)) = Sphere::hit_sphere(self.center(ray.time), self.radius, ray, t_min, t_max) {
             let (u, v) = Sphere::get_sphere_uv(outward_normal);
-            let record: HitRecord = HitRecord::construct(ray.at(root), outward_normal, root, ray, &self.material, u, v);
+            let (tangent, bitangent) = Sphere::get_sphere_tangents(outward_normal, self.radius);
+            let record: HitRecord = HitRecord::construct(ray.at(root), outward_normal, root, ray, &self.material, u, v)
+                .with_tangents(tangent, bitangent);
             Some(record)
         }
         else {
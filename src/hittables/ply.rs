@@ -0,0 +1,459 @@
+use std::{fmt::Display, fs, io, path::Path};
+
+use crate::types::{
+    vec3::Vec3,
+    vec2::{
+        UV,
+        Vec2
+    },
+    color::Color
+};
+
+/// The contents of a .ply file that are used to build a `Mesh`
+/// # Fields
+/// `positions` - one per vertex, from the `x`, `y` and `z` properties
+///
+/// `normals`, `texture_coordinates`, `colors` - per vertex as well, if the vertices have
+/// `nx`/`ny`/`nz`, `u`/`v` (or `s`/`t`, `texture_u`/`texture_v`) and `red`/`green`/`blue` properties.
+/// Integer colors are scaled to the range 0 to 1
+///
+/// `faces` - the vertex indices of each polygon
+///
+/// Any other elements and properties in the file are skipped
+pub struct Ply {
+    pub positions: Vec<Vec3>,
+    pub normals: Option<Vec<Vec3>>,
+    pub texture_coordinates: Option<Vec<UV>>,
+    pub colors: Option<Vec<Color>>,
+    pub faces: Vec<Vec<usize>>
+}
+
+#[derive(Debug)]
+pub enum PlyError {
+    Io(io::Error),
+    /// The header is malformed, or is missing something needed to build a mesh
+    Header(String),
+    /// The data after the header doesn't match what the header describes
+    Body(String)
+}
+
+impl Display for PlyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlyError::Io(error) => write!(f, "{}", error),
+            PlyError::Header(message) => write!(f, "invalid ply header: {}", message),
+            PlyError::Body(message) => write!(f, "invalid ply data: {}", message)
+        }
+    }
+}
+
+impl std::error::Error for PlyError {}
+
+impl From<io::Error> for PlyError {
+    fn from(x: io::Error) -> Self {
+        PlyError::Io(x)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ScalarType {
+    Char,
+    UChar,
+    Short,
+    UShort,
+    Int,
+    UInt,
+    Float,
+    Double
+}
+
+impl ScalarType {
+    fn parse(name: &str) -> Option<ScalarType> {
+        match name {
+            "char" | "int8" => Some(ScalarType::Char),
+            "uchar" | "uint8" => Some(ScalarType::UChar),
+            "short" | "int16" => Some(ScalarType::Short),
+            "ushort" | "uint16" => Some(ScalarType::UShort),
+            "int" | "int32" => Some(ScalarType::Int),
+            "uint" | "uint32" => Some(ScalarType::UInt),
+            "float" | "float32" => Some(ScalarType::Float),
+            "double" | "float64" => Some(ScalarType::Double),
+            _ => None
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            ScalarType::Char | ScalarType::UChar => 1,
+            ScalarType::Short | ScalarType::UShort => 2,
+            ScalarType::Int | ScalarType::UInt | ScalarType::Float => 4,
+            ScalarType::Double => 8
+        }
+    }
+
+    /// What integer values are divided by to bring colors into the range 0 to 1
+    fn color_scale(self) -> f64 {
+        match self {
+            ScalarType::Char => i8::MAX as f64,
+            ScalarType::UChar => u8::MAX as f64,
+            ScalarType::Short => i16::MAX as f64,
+            ScalarType::UShort => u16::MAX as f64,
+            ScalarType::Int => i32::MAX as f64,
+            ScalarType::UInt => u32::MAX as f64,
+            ScalarType::Float | ScalarType::Double => 1.0
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum PropertyType {
+    Scalar(ScalarType),
+    List {
+        count: ScalarType,
+        item: ScalarType
+    }
+}
+
+struct Property {
+    name: String,
+    kind: PropertyType
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>
+}
+
+impl Element {
+    fn property(&self, names: &[&str]) -> Option<usize> {
+        self.properties.iter().position(|property| names.contains(&property.name.as_str()))
+    }
+}
+
+/// Reads the values of the body one at a time, whether they're written as text or binary
+enum Values<'a> {
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    Binary {
+        data: &'a [u8],
+        position: usize,
+        big_endian: bool
+    }
+}
+
+impl Values<'_> {
+    fn read(&mut self, kind: ScalarType) -> Result<f64, PlyError> {
+        match self {
+            Values::Ascii(tokens) => {
+                let token = tokens.next().ok_or_else(|| PlyError::Body("file ends early".to_string()))?;
+                token.parse::<f64>().map_err(|_| PlyError::Body(format!("'{}' isn't a number", token)))
+            },
+            Values::Binary { data, position, big_endian } => {
+                let size = kind.size();
+                let bytes = data.get(*position..*position + size)
+                    .ok_or_else(|| PlyError::Body("file ends early".to_string()))?;
+                *position += size;
+
+                let mut buffer = [0u8; 8];
+                buffer[..size].copy_from_slice(bytes);
+                if *big_endian {
+                    buffer[..size].reverse();
+                }
+                let value = match kind {
+                    ScalarType::Char => i8::from_le_bytes([buffer[0]]) as f64,
+                    ScalarType::UChar => buffer[0] as f64,
+                    ScalarType::Short => i16::from_le_bytes([buffer[0], buffer[1]]) as f64,
+                    ScalarType::UShort => u16::from_le_bytes([buffer[0], buffer[1]]) as f64,
+                    ScalarType::Int => i32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as f64,
+                    ScalarType::UInt => u32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as f64,
+                    ScalarType::Float => f32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as f64,
+                    ScalarType::Double => f64::from_le_bytes(buffer)
+                };
+                Ok(value)
+            }
+        }
+    }
+
+    /// Reads a whole property. Lists are read into `list`, and scalars are returned
+    fn read_property(&mut self, kind: PropertyType, list: &mut Vec<f64>) -> Result<f64, PlyError> {
+        match kind {
+            PropertyType::Scalar(kind) => self.read(kind),
+            PropertyType::List { count, item } => {
+                let length = self.read(count)?;
+                if length < 0.0 || length.fract() != 0.0 {
+                    return Err(PlyError::Body(format!("list length {} isn't a whole number", length)));
+                }
+                list.clear();
+                for _ in 0..length as usize {
+                    list.push(self.read(item)?);
+                }
+                Ok(length)
+            }
+        }
+    }
+}
+
+impl Ply {
+    /// Loads a .ply file, in either the ascii or one of the binary formats
+    pub fn load(path: &Path) -> Result<Ply, PlyError> {
+        Ply::parse(&fs::read(path)?)
+    }
+
+    fn parse(bytes: &[u8]) -> Result<Ply, PlyError> {
+        let (format, elements, body) = Ply::parse_header(bytes)?;
+
+        let mut values = match format {
+            Format::Ascii => {
+                let text = std::str::from_utf8(body).map_err(|_| PlyError::Body("ascii data isn't valid text".to_string()))?;
+                Values::Ascii(text.split_ascii_whitespace())
+            },
+            Format::BinaryLittleEndian | Format::BinaryBigEndian => Values::Binary {
+                data: body,
+                position: 0,
+                big_endian: format == Format::BinaryBigEndian
+            }
+        };
+
+        let mut ply = Ply {
+            positions: Vec::new(),
+            normals: None,
+            texture_coordinates: None,
+            colors: None,
+            faces: Vec::new()
+        };
+        let mut row = Vec::new();
+        let mut list = Vec::new();
+        for element in &elements {
+            match element.name.as_str() {
+                "vertex" => ply.read_vertices(element, &mut values, &mut row, &mut list)?,
+                "face" => ply.read_faces(element, &mut values, &mut list)?,
+                _ => {
+                    for _ in 0..element.count {
+                        for property in &element.properties {
+                            values.read_property(property.kind, &mut list)?;
+                        }
+                    }
+                }
+            }
+        }
+
+        let vertex_count = ply.positions.len();
+        for face in &ply.faces {
+            if let Some(&index) = face.iter().find(|&&index| index >= vertex_count) {
+                return Err(PlyError::Body(format!("face refers to vertex {}, but there are only {}", index, vertex_count)));
+            }
+        }
+        Ok(ply)
+    }
+
+    /// Parses the header, and returns the format and elements it describes along with the rest of the file
+    fn parse_header(bytes: &[u8]) -> Result<(Format, Vec<Element>, &[u8]), PlyError> {
+        let mut format = None;
+        let mut elements: Vec<Element> = Vec::new();
+        let mut position = 0;
+        let mut first = true;
+        loop {
+            let end = bytes[position..].iter().position(|&byte| byte == b'\n')
+                .ok_or_else(|| PlyError::Header("missing 'end_header'".to_string()))?;
+            let line = std::str::from_utf8(&bytes[position..position + end])
+                .map_err(|_| PlyError::Header("header isn't valid text".to_string()))?
+                .trim();
+            position += end + 1;
+
+            let mut words = line.split_ascii_whitespace();
+            let keyword = words.next();
+            if first {
+                if keyword != Some("ply") {
+                    return Err(PlyError::Header("not a ply file".to_string()));
+                }
+                first = false;
+                continue;
+            }
+            match keyword {
+                Some("format") => {
+                    format = match words.next() {
+                        Some("ascii") => Some(Format::Ascii),
+                        Some("binary_little_endian") => Some(Format::BinaryLittleEndian),
+                        Some("binary_big_endian") => Some(Format::BinaryBigEndian),
+                        other => return Err(PlyError::Header(format!("unknown format {:?}", other.unwrap_or(""))))
+                    };
+                },
+                Some("element") => {
+                    let (name, count) = match (words.next(), words.next().and_then(|count| count.parse().ok())) {
+                        (Some(name), Some(count)) => (name, count),
+                        _ => return Err(PlyError::Header(format!("malformed line '{}'", line)))
+                    };
+                    elements.push(Element { name: name.to_string(), count, properties: Vec::new() });
+                },
+                Some("property") => {
+                    let element = elements.last_mut()
+                        .ok_or_else(|| PlyError::Header("property before any element".to_string()))?;
+                    let words: Vec<&str> = words.collect();
+                    let scalar = |name: &str| ScalarType::parse(name)
+                        .ok_or_else(|| PlyError::Header(format!("unknown type '{}'", name)));
+                    let (kind, name) = match words.as_slice() {
+                        ["list", count, item, name] => (PropertyType::List { count: scalar(count)?, item: scalar(item)? }, name),
+                        [kind, name] => (PropertyType::Scalar(scalar(kind)?), name),
+                        _ => return Err(PlyError::Header(format!("malformed line '{}'", line)))
+                    };
+                    element.properties.push(Property { name: name.to_string(), kind });
+                },
+                Some("end_header") => break,
+                Some("comment") | Some("obj_info") | None => {},
+                Some(other) => return Err(PlyError::Header(format!("unknown keyword '{}'", other)))
+            }
+        }
+
+        let format = format.ok_or_else(|| PlyError::Header("missing 'format'".to_string()))?;
+        Ok((format, elements, &bytes[position..]))
+    }
+
+    fn read_vertices(&mut self, element: &Element, values: &mut Values, row: &mut Vec<f64>, list: &mut Vec<f64>) -> Result<(), PlyError> {
+        let required = |names: &[&str]| element.property(names)
+            .ok_or_else(|| PlyError::Header(format!("vertices have no '{}' property", names[0])));
+        let position = [required(&["x"])?, required(&["y"])?, required(&["z"])?];
+        let all = |names: [&[&str]; 3]| Some([element.property(names[0])?, element.property(names[1])?, element.property(names[2])?]);
+        let normal = all([&["nx"], &["ny"], &["nz"]]);
+        let color = all([&["red", "r"], &["green", "g"], &["blue", "b"]]);
+        let texture_coordinates = element.property(&["u", "s", "texture_u"])
+            .zip(element.property(&["v", "t", "texture_v"]));
+        let color_scale = color.map(|[r, g, b]| [r, g, b].map(|index| match element.properties[index].kind {
+            PropertyType::Scalar(kind) => kind.color_scale(),
+            PropertyType::List { .. } => 1.0
+        }));
+
+        let mut normals = Vec::new();
+        let mut uvs = Vec::new();
+        let mut colors = Vec::new();
+        for _ in 0..element.count {
+            row.clear();
+            for property in &element.properties {
+                row.push(values.read_property(property.kind, list)?);
+            }
+            let vector = |[x, y, z]: [usize; 3]| Vec3(row[x], row[y], row[z]);
+            self.positions.push(vector(position));
+            if let Some(normal) = normal {
+                normals.push(vector(normal));
+            }
+            if let Some((u, v)) = texture_coordinates {
+                uvs.push(UV(Vec2(row[u], row[v])));
+            }
+            if let (Some(color), Some([r, g, b])) = (color, color_scale) {
+                colors.push(Vec3(row[color[0]] / r, row[color[1]] / g, row[color[2]] / b));
+            }
+        }
+
+        self.normals = normal.map(|_| normals);
+        self.texture_coordinates = texture_coordinates.map(|_| uvs);
+        self.colors = color.map(|_| colors);
+        Ok(())
+    }
+
+    fn read_faces(&mut self, element: &Element, values: &mut Values, list: &mut Vec<f64>) -> Result<(), PlyError> {
+        let indices = element.property(&["vertex_indices", "vertex_index"])
+            .ok_or_else(|| PlyError::Header("faces have no 'vertex_indices' property".to_string()))?;
+        if let PropertyType::Scalar(_) = element.properties[indices].kind {
+            return Err(PlyError::Header("'vertex_indices' isn't a list".to_string()));
+        }
+
+        let mut corners = Vec::new();
+        for _ in 0..element.count {
+            for (i, property) in element.properties.iter().enumerate() {
+                values.read_property(property.kind, list)?;
+                if i == indices {
+                    corners.clone_from(list);
+                }
+            }
+            let face = corners.iter().map(|&index| {
+                if index < 0.0 || index.fract() != 0.0 {
+                    Err(PlyError::Body(format!("vertex index {} isn't a whole number", index)))
+                } else {
+                    Ok(index as usize)
+                }
+            }).collect::<Result<Vec<usize>, PlyError>>()?;
+            self.faces.push(face);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRIANGLE_POSITIONS: [Vec3; 3] = [Vec3(0.0, 0.0, 0.0), Vec3(1.0, 0.0, 0.0), Vec3(0.0, 1.0, -2.5)];
+
+    /// A single triangle in one of the binary formats, with float positions and an int list of indices
+    fn binary_triangle(format: &str, to_bytes: fn(f32) -> [u8; 4], index_to_bytes: fn(i32) -> [u8; 4]) -> Vec<u8> {
+        let header = format!("ply\nformat {} 1.0\nelement vertex 3\nproperty float x\nproperty float y\nproperty float z\n\
+            element face 1\nproperty list uchar int vertex_indices\nend_header\n", format);
+        let mut bytes = header.into_bytes();
+        for position in TRIANGLE_POSITIONS {
+            for value in [position.x(), position.y(), position.z()] {
+                bytes.extend(to_bytes(value as f32));
+            }
+        }
+        bytes.push(3);
+        for index in [0, 1, 2] {
+            bytes.extend(index_to_bytes(index));
+        }
+        bytes
+    }
+
+    #[test]
+    fn parses_ascii() {
+        let file = "ply\nformat ascii 1.0\ncomment made by hand\n\
+            element vertex 4\nproperty float x\nproperty float y\nproperty float z\n\
+            property float nx\nproperty float ny\nproperty float nz\n\
+            property uchar red\nproperty uchar green\nproperty uchar blue\n\
+            element face 2\nproperty list uchar int vertex_indices\n\
+            element edge 1\nproperty int vertex1\nproperty int vertex2\nend_header\n\
+            0 0 0 0 0 1 255 0 0\n\
+            1 0 0 0 0 1 0 255 0\n\
+            1 1 0 0 0 1 0 0 255\n\
+            0 1 0 0 0 1 255 255 255\n\
+            3 0 1 2\n\
+            4 0 1 2 3\n\
+            0 2\n";
+        let ply = Ply::parse(file.as_bytes()).unwrap();
+        assert_eq!(ply.positions, vec![Vec3(0.0, 0.0, 0.0), Vec3(1.0, 0.0, 0.0), Vec3(1.0, 1.0, 0.0), Vec3(0.0, 1.0, 0.0)]);
+        assert_eq!(ply.normals, Some(vec![Vec3(0.0, 0.0, 1.0); 4]));
+        assert_eq!(ply.colors.unwrap()[1], Vec3(0.0, 1.0, 0.0));
+        assert!(ply.texture_coordinates.is_none());
+        assert_eq!(ply.faces, vec![vec![0, 1, 2], vec![0, 1, 2, 3]]);
+    }
+
+    #[test]
+    fn parses_binary_little_endian() {
+        let ply = Ply::parse(&binary_triangle("binary_little_endian", f32::to_le_bytes, i32::to_le_bytes)).unwrap();
+        assert_eq!(ply.positions, TRIANGLE_POSITIONS.to_vec());
+        assert_eq!(ply.faces, vec![vec![0, 1, 2]]);
+    }
+
+    #[test]
+    fn parses_binary_big_endian() {
+        let ply = Ply::parse(&binary_triangle("binary_big_endian", f32::to_be_bytes, i32::to_be_bytes)).unwrap();
+        assert_eq!(ply.positions, TRIANGLE_POSITIONS.to_vec());
+        assert_eq!(ply.faces, vec![vec![0, 1, 2]]);
+    }
+
+    #[test]
+    fn rejects_out_of_range_vertex_indices() {
+        let file = "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\nproperty float y\nproperty float z\n\
+            element face 1\nproperty list uchar int vertex_indices\nend_header\n\
+            0 0 0\n1 0 0\n0 1 0\n\
+            3 0 1 3\n";
+        match Ply::parse(file.as_bytes()) {
+            Err(PlyError::Body(message)) => assert!(message.contains("vertex 3"), "{}", message),
+            Err(error) => panic!("wrong error: {}", error),
+            Ok(_) => panic!("parsed a face with an out of range index")
+        }
+    }
+}
\ No newline at end of file
@@ -1,11 +1,14 @@
 use std::f64::consts::PI;
 
+use rand::random;
+
 use super::hittable::Hit;
 use crate::types::materials::Material;
 use crate::types::ray::Ray;
 use crate::types::vec3::Point;
 use crate::types::vec3::Vec3;
 use crate::types::aabb::AABB;
+use crate::types::onb::Onb;
 use super::hittable::HitRecord;
 
 // a sphere should indeed outlive the material attached to it
@@ -56,6 +59,34 @@ impl Sphere {
 
         (phi / (2.0 * PI), theta / PI)
     }
+
+    /// Derivatives of a point on a sphere with respect to the (u, v) coordinates from `get_sphere_uv`,
+    /// given the outward normal at that point
+    pub fn get_sphere_tangents(outward_normal: Vec3, radius: f64) -> (Vec3, Vec3) {
+        let Vec3(x, y, z) = outward_normal;
+        let sin_theta = (1.0 - y * y).max(0.0).sqrt();
+        let dpdu = 2.0 * PI * radius * Vec3(z, 0.0, -x);
+        if sin_theta < 1e-8 {
+            // at the poles, v is degenerate - use any direction perpendicular to dpdu
+            return (dpdu, Vec3::cross(outward_normal, Vec3(1.0, 0.0, 0.0)));
+        }
+        let dpdv = PI * radius * Vec3(-x * y / sin_theta, sin_theta, -y * z / sin_theta);
+        (dpdu, dpdv)
+    }
+
+    /// Returns a random direction (in local coordinates, around the z-axis) within the cone
+    /// subtended by a sphere of the given radius, whose center is `distance_squared` away
+    fn random_to_sphere(radius: f64, distance_squared: f64) -> Vec3 {
+        let r1 = random::<f64>();
+        let r2 = random::<f64>();
+        let z = 1.0 + r2 * ((1.0 - radius * radius / distance_squared).sqrt() - 1.0);
+
+        let phi = 2.0 * PI * r1;
+        let x = phi.cos() * (1.0 - z * z).sqrt();
+        let y = phi.sin() * (1.0 - z * z).sqrt();
+
+        Vec3(x, y, z)
+    }
 }
 
 impl Hit for Sphere {
@@ -63,7 +94,9 @@ impl Hit for Sphere {
     fn hit(&self, ray: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
         if let Some((root, outward_normal)) = Sphere::hit_sphere(self.center, self.radius, ray, t_min, t_max) {
             let (u, v) = Sphere::get_sphere_uv(outward_normal);
-            let record: HitRecord = HitRecord::construct(ray.at(root), outward_normal, root, ray, &self.material, u, v);
+            let (tangent, bitangent) = Sphere::get_sphere_tangents(outward_normal, self.radius);
+            let record: HitRecord = HitRecord::construct(ray.at(root), outward_normal, root, ray, &self.material, u, v)
+                .with_tangents(tangent, bitangent);
             Some(record)
         }
         else {
@@ -76,4 +109,38 @@ impl Hit for Sphere {
         let r = Vec3(self.radius, self.radius, self.radius);
         Some(AABB::new(self.center - r, self.center + r))
     }
+
+    /// From outside the sphere, directions are sampled uniformly within the cone the sphere subtends.
+    /// From inside, points are sampled uniformly over the surface
+    fn pdf_value(&self, origin: Point, direction: Vec3) -> f64 {
+        let r = Ray { origin, direction, time: 0.0 };
+        if let Some((t, outward_normal)) = Sphere::hit_sphere(self.center, self.radius, r, 0.001, f64::INFINITY) {
+            let distance_squared = (self.center - origin).square_magnitude();
+            if distance_squared > self.radius * self.radius {
+                let cos_theta_max = (1.0 - self.radius * self.radius / distance_squared).sqrt();
+                let solid_angle = 2.0 * PI * (1.0 - cos_theta_max);
+                1.0 / solid_angle
+            }
+            else {
+                let area = 4.0 * PI * self.radius * self.radius;
+                let cosine = (Vec3::dot(direction, outward_normal) / direction.length()).abs();
+                t * t * direction.square_magnitude() / (cosine * area)
+            }
+        }
+        else {
+            0.0
+        }
+    }
+
+    fn random(&self, origin: Point) -> Vec3 {
+        let direction = self.center - origin;
+        let distance_squared = direction.square_magnitude();
+        if distance_squared > self.radius * self.radius {
+            let uvw = Onb::build_from_w(direction);
+            uvw.local(Sphere::random_to_sphere(self.radius, distance_squared))
+        }
+        else {
+            self.center + self.radius * Vec3::random_unit_vector() - origin
+        }
+    }
 }
\ No newline at end of file
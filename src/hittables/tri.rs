@@ -1,5 +1,7 @@
 use std::f64::EPSILON;
 
+use rand::random;
+
 use crate::types::{
     vec3::{
         Point, 
@@ -29,6 +31,18 @@ impl Triangle {
         Triangle { v0, v1, v2, material }
     }
 
+    /// Bounding box of the triangle with vertices `v0`, `v1`, `v2`
+    pub fn bounds(v0: Vec3, v1: Vec3, v2: Vec3) -> AABB {
+        let x_min = f64::min(v0.0, f64::min(v1.0, v2.0));
+        let y_min = f64::min(v0.1, f64::min(v1.1, v2.1));
+        let z_min = f64::min(v0.2, f64::min(v1.2, v2.2));
+        let x_max = f64::max(v0.0, f64::max(v1.0, v2.0));
+        let y_max = f64::max(v0.1, f64::max(v1.1, v2.1));
+        let z_max = f64::max(v0.2, f64::max(v1.2, v2.2));
+        
+        AABB::new(Vec3(x_min, y_min, z_min), Vec3(x_max, y_max, z_max))
+    }
+
     /// Implementation of Moller-Trumbore ray-triangle intersection algorithm
     /// Returns: (t, u, v) - t where ray intersects triangle, u / v canonical barycentric coordinates
     /// or None if ray does not intersect triangle 
@@ -87,17 +101,38 @@ impl Hit for Triangle {
             &self.material, 
             u, 
             v
-        ))
+        ).with_tangents(e1, e2))
     }
 
     fn bounding_box(&self, _: f64, _: f64) -> Option<AABB> {
-        let x_min = f64::min(self.v0.0, f64::min(self.v1.0, self.v2.0));
-        let y_min = f64::min(self.v0.1, f64::min(self.v1.1, self.v2.1));
-        let z_min = f64::min(self.v0.2, f64::min(self.v1.2, self.v2.2));
-        let x_max = f64::max(self.v0.0, f64::max(self.v1.0, self.v2.0));
-        let y_max = f64::max(self.v0.1, f64::max(self.v1.1, self.v2.1));
-        let z_max = f64::max(self.v0.2, f64::max(self.v1.2, self.v2.2));
-        
-        Some(AABB::new(Vec3(x_min, y_min, z_min), Vec3(x_max, y_max, z_max)))
+        Some(Triangle::bounds(self.v0, self.v1, self.v2))
+    }
+
+    /// Converts the area density of picking a point uniformly on the triangle 
+    /// into a density with respect to solid angle as seen from `origin`
+    fn pdf_value(&self, origin: Point, direction: Vec3) -> f64 {
+        let r = Ray { origin, direction, time: 0.0 };
+        if let Some((t, _, _)) = Triangle::moller_trumbore(self.v0, self.v1, self.v2, r, 0.001, f64::INFINITY) {
+            let cross = Vec3::cross(self.v1 - self.v0, self.v2 - self.v0);
+            let area = 0.5 * cross.length();
+            let distance_squared = t * t * direction.square_magnitude();
+            let cosine = (Vec3::dot(direction, cross) / (direction.length() * cross.length())).abs();
+            distance_squared / (cosine * area)
+        }
+        else {
+            0.0
+        }
+    }
+
+    /// Uniformly samples a point on the triangle by folding a point in the unit square
+    fn random(&self, origin: Point) -> Vec3 {
+        let mut u = random::<f64>();
+        let mut v = random::<f64>();
+        if u + v > 1.0 {
+            u = 1.0 - u;
+            v = 1.0 - v;
+        }
+        let random_point = self.v0 + u * (self.v1 - self.v0) + v * (self.v2 - self.v0);
+        random_point - origin
     }
 }
\ No newline at end of file
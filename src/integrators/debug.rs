@@ -0,0 +1,93 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{
+        Hash,
+        Hasher
+    }
+};
+
+use crate::{
+    scene::Scene,
+    hittables::hittable::Hit,
+    types::{
+        vec3::Vec3,
+        color::Color,
+        ray::Ray
+    }
+};
+
+use super::Integrator;
+
+/// What a `DebugIntegrator` shows at the first hit along each camera ray
+#[derive(Debug, Clone, Copy)]
+pub enum DebugView {
+    /// The shading normal (after any normal or bump maps), mapped from [-1, 1] to [0, 1] on each axis
+    Normals,
+    /// Texture coordinates, u in red and v in green
+    Uv,
+    /// Distance from the camera, white up close and fading to black at `max_distance`
+    Depth {
+        max_distance: f64
+    },
+    /// Green where rays hit the front of a surface, red where they hit the back
+    FrontFace,
+    /// A color picked at random for each material, so every surface sharing a material is the same color
+    MaterialId,
+    /// How much of the hemisphere around the normal is open out to `distance`, from black (covered) to white
+    AmbientOcclusion {
+        distance: f64
+    }
+}
+
+/// Shows some property of the surfaces the camera sees, rather than lighting them. Misses are black.
+/// Like any other render, the values are gamma corrected when the image is saved
+#[derive(Debug, Clone, Copy)]
+pub struct DebugIntegrator {
+    pub view: DebugView
+}
+
+impl Integrator for DebugIntegrator {
+    fn ray_color(&self, r: Ray, scene: &Scene) -> Color {
+        let mut record = match scene.world.hit(r, 0.001, f64::INFINITY) {
+            Some(record) => record,
+            None => return Vec3(0.0, 0.0, 0.0)
+        };
+        let material = record.material;
+        match self.view {
+            DebugView::Normals => {
+                material.resolve(&mut record);
+                0.5 * (record.normal + Vec3(1.0, 1.0, 1.0))
+            },
+            DebugView::Uv => Vec3(record.u, record.v, 0.0),
+            DebugView::Depth { max_distance } => {
+                let distance = record.t * r.direction.length();
+                let shade = (1.0 - distance / max_distance).clamp(0.0, 1.0);
+                Vec3(shade, shade, shade)
+            },
+            DebugView::FrontFace => if record.front_face { Vec3(0.0, 1.0, 0.0) } else { Vec3(1.0, 0.0, 0.0) },
+            DebugView::MaterialId => material_color(material.id()),
+            DebugView::AmbientOcclusion { distance } => {
+                material.resolve(&mut record);
+                // cosine weighted, so that the average over many samples is the open fraction of the hemisphere
+                let mut direction = record.normal + Vec3::random_unit_vector();
+                if direction.near_zero() {
+                    direction = record.normal;
+                }
+                let occlusion_ray = Ray { origin: record.p, direction: Vec3::normalized(direction), time: r.time };
+                match scene.world.hit(occlusion_ray, 0.001, distance) {
+                    Some(_) => Vec3(0.0, 0.0, 0.0),
+                    None => Vec3(1.0, 1.0, 1.0)
+                }
+            }
+        }
+    }
+}
+
+/// Bright color picked by hashing `id`
+fn material_color(id: usize) -> Color {
+    let mut hasher = DefaultHasher::new();
+    id.hash(&mut hasher);
+    let hash = hasher.finish();
+    let channel = |shift: u32| 0.25 + 0.75 * ((hash >> shift) & 0xff) as f64 / 255.0;
+    Vec3(channel(0), channel(8), channel(16))
+}
\ No newline at end of file
@@ -0,0 +1,69 @@
+use clap::clap_derive::ArgEnum;
+
+use crate::{
+    scene::Scene,
+    hittables::hittable::Hit,
+    types::{
+        color::Color,
+        ray::Ray
+    }
+};
+
+pub mod path;
+pub mod debug;
+
+pub use self::{
+    path::{
+        PathTracer,
+        Bounces
+    },
+    debug::{
+        DebugIntegrator,
+        DebugView
+    }
+};
+
+/// Works out the color seen along camera rays
+pub trait Integrator: Send + Sync {
+    /// Light (or for debug views, whatever is being visualized) arriving back along `r`
+    fn ray_color(&self, r: Ray, scene: &Scene) -> Color;
+}
+
+/// The integrators which can be picked from the command line
+#[derive(Debug, Clone, Copy, ArgEnum, PartialEq)]
+pub enum IntegratorKind {
+    /// Physically based rendering, see `PathTracer`
+    Path,
+    Normals,
+    Uv,
+    Depth,
+    FrontFace,
+    MaterialId,
+    AmbientOcclusion
+}
+
+impl IntegratorKind {
+    /// Builds the integrator for `scene`
+    /// # Arguments
+    /// `path_tracer` - what's returned for `Path`
+    ///
+    /// `distance` - distance at which the depth view fades to black, and how far ambient occlusion rays reach.
+    /// Defaults to the size of the scene for the depth view, and a tenth of it for ambient occlusion
+    pub fn build(self, scene: &Scene, path_tracer: PathTracer, distance: Option<f64>) -> Box<dyn Integrator> {
+        // unbounded scenes (e.g with an infinite plane) fall back on a distance of 1
+        let scene_size = scene.world.bounding_box(0.0, 0.0)
+            .map(|aabb| (aabb.maximum - aabb.minimum).length())
+            .filter(|size| size.is_finite() && *size > 0.0)
+            .unwrap_or(1.0);
+        let view = match self {
+            IntegratorKind::Path => return Box::new(path_tracer),
+            IntegratorKind::Normals => DebugView::Normals,
+            IntegratorKind::Uv => DebugView::Uv,
+            IntegratorKind::Depth => DebugView::Depth { max_distance: distance.unwrap_or(scene_size) },
+            IntegratorKind::FrontFace => DebugView::FrontFace,
+            IntegratorKind::MaterialId => DebugView::MaterialId,
+            IntegratorKind::AmbientOcclusion => DebugView::AmbientOcclusion { distance: distance.unwrap_or(0.1 * scene_size) }
+        };
+        Box::new(DebugIntegrator { view })
+    }
+}
\ No newline at end of file
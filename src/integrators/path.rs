@@ -0,0 +1,231 @@
+use rand::random;
+
+use crate::{
+    scene::Scene,
+    hittables::hittable::{
+        Hit,
+        HitRecord
+    },
+    types::{
+        vec3::Vec3,
+        color::Color,
+        ray::Ray,
+        materials::{
+            Material,
+            ScatterRecord,
+            ScatterKind
+        }
+    }
+};
+
+use super::Integrator;
+
+/// Unidirectional path tracer, which samples lights directly at every bounce and weights that against
+/// finding them by scattering with multiple importance sampling
+/// # Fields
+/// `max_depth` - how many bounces, in total and of each kind, a path can take before it's cut off.
+/// The last hit is still lit directly (by sampling lights and by the light its scattered ray finds
+/// without going any further), so a limit of 0 diffuse bounces gives direct lighting only
+///
+/// `roulette_depth` - bounces after which paths are randomly ended with Russian roulette, more likely the less
+/// light they carry. Surviving paths are brightened to make up for the ones ended, so this adds noise but no bias
+#[derive(Debug, Clone, Copy)]
+pub struct PathTracer {
+    pub max_depth: Bounces,
+    pub roulette_depth: u32
+}
+
+impl Default for PathTracer {
+    fn default() -> Self {
+        PathTracer {
+            max_depth: Bounces { total: 64, diffuse: 16, specular: 16, transmission: 32, volume: 64 },
+            roulette_depth: 3
+        }
+    }
+}
+
+/// A number of bounces, in total and of each `ScatterKind`. Used both for how far along a path is, and how far it can go
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Bounces {
+    pub total: u32,
+    pub diffuse: u32,
+    pub specular: u32,
+    pub transmission: u32,
+    pub volume: u32
+}
+
+impl Bounces {
+    /// Counts one more bounce of `kind`
+    fn after(mut self, kind: ScatterKind) -> Bounces {
+        self.total += 1;
+        match kind {
+            ScatterKind::Diffuse => self.diffuse += 1,
+            ScatterKind::Specular => self.specular += 1,
+            ScatterKind::Transmission => self.transmission += 1,
+            ScatterKind::Volume => self.volume += 1
+        }
+        self
+    }
+
+    /// Whether none of these counts are over those of `limit`
+    fn within(&self, limit: &Bounces) -> bool {
+        self.total <= limit.total
+            && self.diffuse <= limit.diffuse
+            && self.specular <= limit.specular
+            && self.transmission <= limit.transmission
+            && self.volume <= limit.volume
+    }
+}
+
+impl Integrator for PathTracer {
+    fn ray_color(&self, r: Ray, scene: &Scene) -> Color {
+        self.path_color(r, scene, Bounces::default(), Vec3(1.0, 1.0, 1.0), None)
+    }
+}
+
+/// Weight given to a sample taken with density `pdf`, when it could also have been sampled
+/// by another technique with density `other_pdf`
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let a = pdf * pdf;
+    let b = other_pdf * other_pdf;
+    if a + b > 0.0 { a / (a + b) } else { 0.0 }
+}
+
+/// The first hit along `r` closer than `t_max` which doesn't let it straight through, with its material resolved.
+/// Rays look through `Material::Transparent` without it counting as a bounce, so they keep their origin and
+/// anything found beyond is weighted just as if there were nothing in the way
+fn hit_surface(r: Ray, scene: &Scene, t_max: f64) -> Option<HitRecord<'_>> {
+    let mut t_min = 0.001;
+    loop {
+        let mut record = scene.world.hit(r, t_min, t_max)?;
+        let material = record.material;
+        material.resolve(&mut record);
+        if !matches!(record.material, Material::Transparent) {
+            return Some(record);
+        }
+        t_min = record.t + 0.001;
+    }
+}
+
+/// Estimate the light arriving at a hit directly from one of the scene's lights by sending a shadow
+/// ray towards it, weighted against the chance of scattering towards that light instead
+fn sample_lights(r: Ray, record: &HitRecord, scene: &Scene) -> Color {
+    let direction = match scene.random_light_direction(record.p) {
+        Some(direction) => direction,
+        None => return Vec3(0.0, 0.0, 0.0)
+    };
+    let (bsdf, bsdf_pdf) = match record.material.eval(r, record, direction) {
+        Some(x) => x,
+        None => return Vec3(0.0, 0.0, 0.0)
+    };
+    let light_pdf = scene.light_pdf(record.p, direction);
+    if light_pdf <= 0.0 {
+        return Vec3(0.0, 0.0, 0.0);
+    }
+
+    let shadow_ray = Ray { origin: record.p, direction, time: r.time };
+    let light = match hit_surface(shadow_ray, scene, f64::INFINITY) {
+        Some(light_record) => light_record.material.emitted(light_record.u, light_record.v, light_record.p).unwrap_or_default(),
+        None if scene.background.is_sampled() => scene.background.get_color(shadow_ray),
+        None => Vec3(0.0, 0.0, 0.0)
+    };
+    light * bsdf * power_heuristic(light_pdf, bsdf_pdf) / light_pdf
+}
+
+/// Light arriving at a hit straight from the scene's point, spot and directional lights, which can only be
+/// found by sending a shadow ray towards each of them
+fn sample_delta_lights(r: Ray, record: &HitRecord, scene: &Scene) -> Color {
+    let mut color = Vec3(0.0, 0.0, 0.0);
+    for light in scene.delta_lights.iter() {
+        let sample = match light.sample(record.p) {
+            Some(sample) => sample,
+            None => continue
+        };
+        let bsdf = match record.material.eval(r, record, sample.direction) {
+            Some((bsdf, _)) => bsdf,
+            None => continue
+        };
+        let shadow_ray = Ray { origin: record.p, direction: sample.direction, time: r.time };
+        if hit_surface(shadow_ray, scene, sample.distance * (1.0 - 1e-6)).is_none() {
+            color += bsdf * sample.irradiance;
+        }
+    }
+    color
+}
+
+/// Light emitted back along `r` by whatever it hits, or the background, without scattering any further.
+/// `scatter_pdf` weights it as in `PathTracer::path_color`
+fn emission_along(r: Ray, scene: &Scene, scatter_pdf: Option<f64>) -> Color {
+    match hit_surface(r, scene, f64::INFINITY) {
+        Some(record) => emitted_along(r, &record, scene, scatter_pdf),
+        None => background_along(r, scene, scatter_pdf)
+    }
+}
+
+/// Light emitted back along `r` from `record`, where it hit. `scatter_pdf` weights it as in `PathTracer::path_color`
+fn emitted_along(r: Ray, record: &HitRecord, scene: &Scene, scatter_pdf: Option<f64>) -> Color {
+    let emitted = record.material.emitted(record.u, record.v, record.p).unwrap_or_default();
+    match scatter_pdf {
+        Some(pdf) => emitted * power_heuristic(pdf, scene.light_pdf(r.origin, r.direction)),
+        None => emitted
+    }
+}
+
+/// The background seen by `r`, which has escaped the scene. `scatter_pdf` weights it as in `PathTracer::path_color`
+fn background_along(r: Ray, scene: &Scene, scatter_pdf: Option<f64>) -> Color {
+    let background = scene.background.get_color(r);
+    match scatter_pdf {
+        // a sampled background is weighted just like any other light
+        Some(pdf) if scene.background.is_sampled() => {
+            background * power_heuristic(pdf, scene.light_pdf(r.origin, r.direction))
+        },
+        _ => background
+    }
+}
+
+impl PathTracer {
+    /// `bounces` - how many bounces the path has already taken to get to `r`
+    ///
+    /// `throughput` - how much of the light found along `r` makes it back to the camera, which decides
+    /// how likely the path is to survive Russian roulette
+    ///
+    /// `scatter_pdf` - density that `r` was scattered with, if it could also have been found by sampling lights.
+    /// Emission found by `r` is then weighted against the chance of having sampled it directly instead
+    fn path_color(&self, r: Ray, scene: &Scene, bounces: Bounces, throughput: Color, scatter_pdf: Option<f64>) -> Color {
+        match hit_surface(r, scene, f64::INFINITY) {
+            Some(record) => {
+                let emitted = emitted_along(r, &record, scene, scatter_pdf);
+                #[cfg(feature="ray_debug")]
+                {
+                    println!("{:?}", r);
+                    println!("{:?}", record);
+                }
+                let ScatterRecord { attenuation, scattered, pdf, kind } = match record.material.scatter(r, &record) {
+                    Some(scatter) => scatter,
+                    // Absorbed
+                    None => return emitted
+                };
+                let direct = emitted
+                    + sample_lights(r, &record, scene)
+                    + sample_delta_lights(r, &record, scene);
+
+                let bounces = bounces.after(kind);
+                if !bounces.within(&self.max_depth) {
+                    // the light found by scattering makes up the rest of the estimate for the lights sampled above
+                    return direct + attenuation * emission_along(scattered, scene, pdf);
+                }
+                let mut throughput = throughput * attenuation;
+                let mut survival = 1.0;
+                if bounces.total > self.roulette_depth {
+                    survival = f64::max(throughput.x(), f64::max(throughput.y(), throughput.z())).min(1.0);
+                    if random::<f64>() >= survival {
+                        return direct;
+                    }
+                    throughput /= survival;
+                }
+                direct + attenuation * self.path_color(scattered, scene, bounces, throughput, pdf) / survival
+            },
+            None => background_along(r, scene, scatter_pdf)
+        }
+    }
+}
\ No newline at end of file
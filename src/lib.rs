@@ -0,0 +1,20 @@
+pub mod types;
+pub mod hittables;
+pub mod utils;
+pub mod camera;
+pub mod scene;
+pub mod light;
+pub mod preset_scenes;
+pub mod scene_file;
+pub mod gltf_scene;
+pub mod integrators;
+pub mod render;
+
+pub use render::{
+    render, 
+    render_with_integrator, 
+    Image, 
+    RenderSettings, 
+    RenderStrategy
+};
+pub use scene::Scene;
\ No newline at end of file
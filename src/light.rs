@@ -0,0 +1,127 @@
+use crate::types::{
+    color::Color,
+    vec3::{
+        Point,
+        Vec3
+    }
+};
+
+/// A light which isn't part of the world, so it can't be hit and is only found by sampling it directly
+#[derive(Debug, Clone, Copy)]
+pub enum Light {
+    /// Shines `intensity` (radiant intensity) equally in every direction, falling off with the square of the distance
+    Point {
+        position: Point,
+        intensity: Color
+    },
+    /// A point light which only shines in a cone around `direction` (which is normalized). It's at full
+    /// strength inside `cos_inner`, and falls off smoothly to nothing at `cos_outer`
+    Spot {
+        position: Point,
+        direction: Vec3,
+        intensity: Color,
+        cos_inner: f64,
+        cos_outer: f64
+    },
+    /// Light arriving from infinitely far away, travelling along `direction` (which is normalized),
+    /// e.g the sun. `irradiance` is what lands on a surface facing it
+    Directional {
+        direction: Vec3,
+        irradiance: Color
+    }
+}
+
+/// Light arriving at a point from a `Light`
+/// # Fields
+/// `direction` - unit direction from the point towards the light
+///
+/// `distance` - how far away the light is, infinity for directional lights
+///
+/// `irradiance` - light arriving on a surface facing the light, before any shadowing
+#[derive(Debug, Clone, Copy)]
+pub struct LightSample {
+    pub direction: Vec3,
+    pub distance: f64,
+    pub irradiance: Color
+}
+
+impl Light {
+    /// Point light at `position`
+    pub fn point(position: Point, intensity: Color) -> Light {
+        Light::Point { position, intensity }
+    }
+
+    /// Spot light at `position` pointing along `direction`. `inner_angle` and `outer_angle` are the angles (in degrees)
+    /// from `direction` where the light starts to fall off and where it ends, `inner_angle` is clamped to `outer_angle`
+    pub fn spot(position: Point, direction: Vec3, intensity: Color, inner_angle: f64, outer_angle: f64) -> Light {
+        Light::Spot {
+            position,
+            direction: Vec3::normalized(direction),
+            intensity,
+            cos_inner: inner_angle.min(outer_angle).to_radians().cos(),
+            cos_outer: outer_angle.to_radians().cos()
+        }
+    }
+
+    /// Directional light travelling along `direction`
+    pub fn directional(direction: Vec3, irradiance: Color) -> Light {
+        Light::Directional { direction: Vec3::normalized(direction), irradiance }
+    }
+
+    /// Light arriving at `p` from this light, None if none of it reaches `p` (even without anything in the way)
+    pub fn sample(&self, p: Point) -> Option<LightSample> {
+        let (direction, distance, irradiance) = match *self {
+            Light::Point { position, intensity } => {
+                let to_light = position - p;
+                let distance = to_light.length();
+                (to_light / distance, distance, intensity / (distance * distance))
+            },
+            Light::Spot { position, direction, intensity, cos_inner, cos_outer } => {
+                let to_light = position - p;
+                let distance = to_light.length();
+                let to_light = to_light / distance;
+                let falloff = spot_falloff(Vec3::dot(-to_light, direction), cos_inner, cos_outer);
+                (to_light, distance, falloff * intensity / (distance * distance))
+            },
+            Light::Directional { direction, irradiance } => (-direction, f64::INFINITY, irradiance)
+        };
+        // only lights giving exactly nothing are skipped, since distant lights can be very dim but still matter
+        if distance <= 0.0 || irradiance == Vec3(0.0, 0.0, 0.0) {
+            return None;
+        }
+        Some(LightSample { direction, distance, irradiance })
+    }
+}
+
+/// Fraction of a spot light's intensity shone at an angle with cosine `cos_theta` from its direction
+fn spot_falloff(cos_theta: f64, cos_inner: f64, cos_outer: f64) -> f64 {
+    if cos_theta >= cos_inner {
+        1.0
+    }
+    else if cos_theta <= cos_outer {
+        0.0
+    }
+    else {
+        let t = (cos_theta - cos_outer) / (cos_inner - cos_outer);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distant_point_lights_are_sampled() {
+        let light = Light::point(Vec3(0.0, 1e4, 0.0), Vec3(1.0, 1.0, 1.0));
+        let sample = light.sample(Vec3(0.0, 0.0, 0.0)).expect("a dim light is still a light");
+        assert!((sample.irradiance.x() - 1e-8).abs() < 1e-20);
+    }
+
+    #[test]
+    fn spot_lights_give_nothing_outside_their_cone() {
+        let light = Light::spot(Vec3(0.0, 1.0, 0.0), Vec3(0.0, -1.0, 0.0), Vec3(1.0, 1.0, 1.0), 10.0, 20.0);
+        assert!(light.sample(Vec3(0.0, 0.0, 0.0)).is_some());
+        assert!(light.sample(Vec3(5.0, 0.0, 0.0)).is_none());
+    }
+}
@@ -3,8 +3,8 @@ use std::{sync::Arc, path::Path};
 use clap::clap_derive::ArgEnum;
 use rand::random;
 
-use crate::{scene::Scene, types::{vec3::Vec3, texture::{CheckerTexture, SolidColor, Texture, NoiseTexture, ImageTexture}, color, materials::Material, transform::TransformData, bvh::BVHNode}, camera::Camera, hittables::{hittable_list::HittableList, sphere::Sphere, moving_sphere::MovingSphere, aarect::{YZ, XZ, XY}, block::Block, instance::Instance, constant_medium::ConstantMedium, hittable::Hit, tri::Triangle, mesh::Mesh}, utils::{random_range, degrees_to_radians}, Background, hittable_list};
-use crate::Material::*;
+use crate::{scene::Scene, types::{vec3::Vec3, texture::{CheckerTexture, SolidColor, Texture, NoiseTexture, ImageTexture}, color, materials::{Material, MixFactor, NormalPerturbation, Absorption, microfacet::ComplexIor, principled::PrincipledBsdf}, transform::TransformData, bvh::{BVH, SplitStrategy}}, camera::Camera, hittables::{hittable_list::HittableList, sphere::Sphere, moving_sphere::MovingSphere, aarect::{YZ, XZ, XY}, block::Block, instance::Instance, constant_medium::ConstantMedium, hittable::Hit, tri::Triangle, mesh::Mesh}, utils::{random_range, degrees_to_radians}, scene::Background, hittable_list};
+use crate::types::materials::Material::*;
 
 #[derive(Clone, ArgEnum)]
 pub enum PresetScene {
@@ -19,10 +19,12 @@ pub enum PresetScene {
     FinalRender,
     TriangleTest,
     MeshTest,
+    MaterialShowcase,
 }
 
 impl PresetScene {
-    pub fn get(&self, samples_per_pixel: u32) -> Scene {
+    /// Builds the scene, splitting any BVHs in it with `split`
+    pub fn get(&self, samples_per_pixel: u32, split: SplitStrategy) -> Scene {
         match self {
             PresetScene::JumpingBalls => random_scene(samples_per_pixel),
             PresetScene::TwoSpheres => two_spheres(samples_per_pixel),
@@ -32,9 +34,10 @@ impl PresetScene {
             PresetScene::CornellBox => cornell_box(samples_per_pixel),
             PresetScene::TransformTest => transform_test(samples_per_pixel),
             PresetScene::CornellSmoke => cornell_smoke(samples_per_pixel),
-            PresetScene::FinalRender => final_scene(samples_per_pixel),
+            PresetScene::FinalRender => final_scene(samples_per_pixel, split),
             PresetScene::TriangleTest => triangle_test(samples_per_pixel),
-            PresetScene::MeshTest => mesh_test(samples_per_pixel)
+            PresetScene::MeshTest => mesh_test(samples_per_pixel),
+            PresetScene::MaterialShowcase => material_showcase(samples_per_pixel)
         }
     }
 }
@@ -105,7 +108,7 @@ pub fn random_scene(samples_per_pixel: u32) -> Scene {
                     }));
                 } else {
                     // glass
-                    sphere_material = Dielectric { index_of_refraction: 1.5 };
+                    sphere_material = Dielectric { index_of_refraction: 1.5, absorption: None };
                     world.add(Box::new(Sphere {
                         center, 
                         radius: 0.2, 
@@ -117,7 +120,8 @@ pub fn random_scene(samples_per_pixel: u32) -> Scene {
     }
 
     let material1 =  Dielectric {
-        index_of_refraction: 1.5
+        index_of_refraction: 1.5,
+        absorption: None
     };
     world.add(Box::new(Sphere { 
         center: Vec3(0.0, 1.0, 0.0), 
@@ -147,6 +151,8 @@ pub fn random_scene(samples_per_pixel: u32) -> Scene {
 
     Scene {
         world,
+        lights: HittableList::new(),
+        delta_lights: Vec::new(),
         camera,
         aspect_ratio: ASPECT_RATIO,
         height: IMAGE_HEIGHT,
@@ -180,6 +186,8 @@ fn diagonal_view(samples_per_pixel: u32, world: HittableList) -> Scene {
     Scene { 
         camera, 
         world, 
+        lights: HittableList::new(),
+        delta_lights: Vec::new(),
         aspect_ratio: ASPECT_RATIO, 
         height: IMAGE_HEIGHT, 
         width: IMAGE_WIDTH, 
@@ -211,6 +219,8 @@ fn straight_view(samples_per_pixel: u32, world: HittableList) -> Scene {
     Scene { 
         camera, 
         world, 
+        lights: HittableList::new(),
+        delta_lights: Vec::new(),
         aspect_ratio: ASPECT_RATIO, 
         height: IMAGE_HEIGHT, 
         width: IMAGE_WIDTH, 
@@ -290,16 +300,18 @@ pub fn simple_light(samples_per_pixel: u32) -> Scene {
     let diffuse_light = DiffuseLight { 
         emit: Arc::new(SolidColor::from(Vec3(4.0, 4.0, 4.0)))
     };
-    let rect_light = XY {
+    // shared between the world and the lights
+    let rect_light = Arc::new(XY {
         a0: 3.0,
         a1: 5.0,
         b0: 1.0,
         b1: 3.0,
         k: -2.0,
         material: diffuse_light
-    };
+    });
 
-    let world = hittable_list!(Box::new(bottom_sphere), Box::new(top_sphere), Box::new(rect_light));
+    let world = hittable_list!(Box::new(bottom_sphere), Box::new(top_sphere), Box::new(rect_light.clone()));
+    let lights = hittable_list!(Box::new(rect_light));
 
     pub const ASPECT_RATIO: f64 = 16.0 / 9.0;
     const IMAGE_WIDTH: u32 = 400;
@@ -323,6 +335,8 @@ pub fn simple_light(samples_per_pixel: u32) -> Scene {
     Scene { 
         camera, 
         world, 
+        lights,
+        delta_lights: Vec::new(),
         aspect_ratio: ASPECT_RATIO, 
         height: IMAGE_HEIGHT, 
         width: IMAGE_WIDTH, 
@@ -339,7 +353,7 @@ pub fn cornell_box(samples_per_pixel: u32) -> Scene {
     let white_material = Arc::new(SolidColor::from(Vec3(0.73, 0.73, 0.73)));
     let white = Lambertian {
         albedo: white_material
-    };
+    }.shared();
     let green = Lambertian {
         albedo: Arc::new(SolidColor::from(Vec3(0.12, 0.45, 0.15)))
     };
@@ -363,14 +377,15 @@ pub fn cornell_box(samples_per_pixel: u32) -> Scene {
         k: 0.0,
         material: red
     };
-    let wall2 = XZ {
+    let wall2 = Arc::new(XZ {
         a0: 213.0,
         a1: 343.0,
         b0: 227.0,
         b1: 332.0,
         k: 554.0,
         material: light
-    };
+    });
+    let lights = hittable_list!(Box::new(wall2.clone()));
     let wall3 = XZ {
         a0: 0.0,
         a1: 555.0,
@@ -455,6 +470,8 @@ pub fn cornell_box(samples_per_pixel: u32) -> Scene {
     Scene { 
         camera, 
         world, 
+        lights,
+        delta_lights: Vec::new(),
         aspect_ratio: ASPECT_RATIO, 
         height: IMAGE_HEIGHT, 
         width: IMAGE_WIDTH, 
@@ -471,7 +488,7 @@ pub fn transform_test(samples_per_pixel: u32) -> Scene {
     let white_material = Arc::new(SolidColor::from(Vec3(0.73, 0.73, 0.73)));
     let white = Lambertian {
         albedo: white_material
-    };
+    }.shared();
     let green = Lambertian {
         albedo: Arc::new(SolidColor::from(Vec3(0.12, 0.45, 0.15)))
     };
@@ -495,14 +512,15 @@ pub fn transform_test(samples_per_pixel: u32) -> Scene {
         k: 0.0,
         material: red
     };
-    let wall2 = XZ {
+    let wall2 = Arc::new(XZ {
         a0: 163.0,
         a1: 393.0,
         b0: 177.0,
         b1: 382.0,
         k: 554.0,
         material: light
-    };
+    });
+    let lights = hittable_list!(Box::new(wall2.clone()));
     let wall3 = XZ {
         a0: 0.0,
         a1: 555.0,
@@ -572,6 +590,8 @@ pub fn transform_test(samples_per_pixel: u32) -> Scene {
     Scene { 
         camera, 
         world, 
+        lights,
+        delta_lights: Vec::new(),
         aspect_ratio: ASPECT_RATIO, 
         height: IMAGE_HEIGHT, 
         width: IMAGE_WIDTH, 
@@ -588,7 +608,7 @@ pub fn cornell_smoke(samples_per_pixel: u32) -> Scene {
     let white_material = Arc::new(SolidColor::from(Vec3(0.73, 0.73, 0.73)));
     let white = Lambertian {
         albedo: white_material
-    };
+    }.shared();
     let green = Lambertian {
         albedo: Arc::new(SolidColor::from(Vec3(0.12, 0.45, 0.15)))
     };
@@ -612,14 +632,15 @@ pub fn cornell_smoke(samples_per_pixel: u32) -> Scene {
         k: 0.0,
         material: red
     };
-    let wall2 = XZ {
+    let wall2 = Arc::new(XZ {
         a0: 113.0,
         a1: 443.0,
         b0: 127.0,
         b1: 432.0,
         k: 554.0,
         material: light
-    };
+    });
+    let lights = hittable_list!(Box::new(wall2.clone()));
     let wall3 = XZ {
         a0: 0.0,
         a1: 555.0,
@@ -707,6 +728,8 @@ pub fn cornell_smoke(samples_per_pixel: u32) -> Scene {
     Scene { 
         camera, 
         world, 
+        lights,
+        delta_lights: Vec::new(),
         aspect_ratio: ASPECT_RATIO, 
         height: IMAGE_HEIGHT, 
         width: IMAGE_WIDTH, 
@@ -715,7 +738,7 @@ pub fn cornell_smoke(samples_per_pixel: u32) -> Scene {
     }
 }
 
-pub fn final_scene(samples_per_pixel: u32) -> Scene {
+pub fn final_scene(samples_per_pixel: u32, split: SplitStrategy) -> Scene {
     let mut boxes1: Vec<Box<dyn Hit>> = Vec::new();
     let ground = Lambertian { 
         albedo: Arc::new(SolidColor::from(Vec3(0.48, 0.83, 0.53)))
@@ -737,20 +760,22 @@ pub fn final_scene(samples_per_pixel: u32) -> Scene {
     }
     
     let mut objects = HittableList::new();
-    objects.add(Box::new(BVHNode::make(boxes1, 0.0, 1.0)));
+    objects.add(Box::new(BVH::make_with_strategy(boxes1, 0.0, 1.0, split)));
 
     let light = DiffuseLight { 
         emit: Arc::new(SolidColor::from(Vec3(7.0, 7.0, 7.0)))  
     };
 
-    objects.add(Box::new(XZ {
+    let light_rect = Arc::new(XZ {
         material: light,
         a0: 123.0,
         a1: 423.0,
         b0: 147.0,
         b1: 412.0,
         k: 554.0,
-    }));
+    });
+    objects.add(Box::new(light_rect.clone()));
+    let lights = hittable_list!(Box::new(light_rect));
 
     let center1 = Vec3(400.0, 400.0, 200.0);
     let center2 = center1 + Vec3(30.0, 0.0, 0.0);
@@ -769,7 +794,7 @@ pub fn final_scene(samples_per_pixel: u32) -> Scene {
     objects.add(Box::new(Sphere {
         center: Vec3(260.0, 150.0, 45.0),
         radius: 50.0,
-        material: Dielectric { index_of_refraction: 1.5 },
+        material: Dielectric { index_of_refraction: 1.5, absorption: None },
     }));
 
     objects.add(Box::new(Sphere {
@@ -781,7 +806,7 @@ pub fn final_scene(samples_per_pixel: u32) -> Scene {
     let boundary = Box::new(Sphere {
         center: Vec3(360.0, 150.0, 145.0),
         radius: 70.0,
-        material: Dielectric { index_of_refraction: 1.5 }
+        material: Dielectric { index_of_refraction: 1.5, absorption: None }
     });
 
     objects.add(boundary);
@@ -789,7 +814,7 @@ pub fn final_scene(samples_per_pixel: u32) -> Scene {
     let boundary = Box::new(Sphere {
         center: Vec3(360.0, 150.0, 145.0),
         radius: 70.0,
-        material: Dielectric { index_of_refraction: 1.5 }
+        material: Dielectric { index_of_refraction: 1.5, absorption: None }
     });
     objects.add(Box::new(ConstantMedium::solid(
         boundary,
@@ -800,7 +825,7 @@ pub fn final_scene(samples_per_pixel: u32) -> Scene {
     let boundary = Box::new(Sphere {
         center: Vec3(0.0, 0.0, 0.0),
         radius: 5000.0,
-        material: Dielectric { index_of_refraction: 1.5 }
+        material: Dielectric { index_of_refraction: 1.5, absorption: None }
     });
     objects.add(Box::new(ConstantMedium::solid(
         boundary, 
@@ -830,7 +855,7 @@ pub fn final_scene(samples_per_pixel: u32) -> Scene {
     let mut boxes2: Vec<Box<dyn Hit>> = Vec::new();
     let white = Lambertian { 
         albedo: Arc::new(SolidColor::from(Vec3(0.73, 0.73, 0.73)))
-    };
+    }.shared();
 
     let ns: i32 = 1000;
     for _ in 0..ns {
@@ -842,7 +867,7 @@ pub fn final_scene(samples_per_pixel: u32) -> Scene {
     }
 
     objects.add(Box::new(Instance::new(
-        Box::new(BVHNode::make(boxes2, 0.0, 1.0)),
+        Box::new(BVH::make_with_strategy(boxes2, 0.0, 1.0, split)),
         TransformData::identity().rotate_angle_axis(Vec3(0.0, 1.0, 0.0), degrees_to_radians(15.0))
                                             .translate(Vec3(-100.0, 270.0, 395.0))
     )));
@@ -870,6 +895,8 @@ pub fn final_scene(samples_per_pixel: u32) -> Scene {
     Scene { 
         camera, 
         world: objects, 
+        lights,
+        delta_lights: Vec::new(),
         aspect_ratio: ASPECT_RATIO, 
         height: IMAGE_HEIGHT, 
         width: IMAGE_WIDTH, 
@@ -901,4 +928,114 @@ pub fn mesh_test(samples_per_pixel: u32) -> Scene {
     let world = hittable_list!(Box::new(mesh));
 
     straight_view(samples_per_pixel, world)
+}
+
+/// A row of spheres under a single light, one per material
+pub fn material_showcase(samples_per_pixel: u32) -> Scene {
+    let floor = Mix {
+        first: Box::new(Lambertian { 
+            albedo: Arc::new(CheckerTexture::make_solid_checkered(Vec3(0.2, 0.2, 0.2), Vec3(0.8, 0.8, 0.8))) 
+        }),
+        second: Box::new(Metal { albedo: Vec3(0.9, 0.9, 0.9), fuzz: 0.05 }),
+        factor: MixFactor::Constant(0.25)
+    };
+
+    let materials = vec![
+        Lambertian { 
+            albedo: Arc::new(SolidColor::from(Vec3(0.65, 0.2, 0.15))) 
+        },
+        // dirty metal
+        Mix {
+            first: Box::new(Metal { albedo: Vec3(0.8, 0.6, 0.4), fuzz: 0.1 }),
+            second: Box::new(Lambertian { albedo: Arc::new(SolidColor::from(Vec3(0.3, 0.25, 0.2))) }),
+            factor: MixFactor::Texture(Arc::new(NoiseTexture::new(4.0)))
+        },
+        // green tinted glass
+        Dielectric { 
+            index_of_refraction: 1.5, 
+            absorption: Some(Absorption { color: Vec3(0.4, 0.8, 0.5), distance: 1.0 }) 
+        },
+        Conductor {
+            tint: Arc::new(SolidColor::from(Vec3(1.0, 1.0, 1.0))),
+            roughness: Arc::new(SolidColor::from(Vec3(0.3, 0.3, 0.3))),
+            ior: ComplexIor::GOLD
+        },
+        // brushed copper, with patches of varying roughness
+        Conductor {
+            tint: Arc::new(SolidColor::from(Vec3(1.0, 1.0, 1.0))),
+            roughness: Arc::new(NoiseTexture::new(2.0)),
+            ior: ComplexIor::COPPER
+        },
+        RoughDielectric { 
+            index_of_refraction: 1.5, 
+            roughness: Arc::new(SolidColor::from(Vec3(0.2, 0.2, 0.2))),
+            absorption: None
+        },
+        // clearcoated car paint
+        Principled(Box::new(PrincipledBsdf {
+            roughness: Arc::new(SolidColor::from(Vec3(0.4, 0.4, 0.4))),
+            clearcoat: Arc::new(SolidColor::from(Vec3(1.0, 1.0, 1.0))),
+            ..PrincipledBsdf::new(Arc::new(SolidColor::from(Vec3(0.1, 0.2, 0.6))))
+        })),
+        // hammered steel
+        Perturbed {
+            material: Box::new(Metal { albedo: Vec3(0.7, 0.7, 0.75), fuzz: 0.05 }),
+            perturbation: NormalPerturbation::BumpMap { texture: Arc::new(NoiseTexture::new(8.0)), scale: 0.02 }
+        },
+    ];
+
+    let mut world = HittableList::new();
+    world.add(Box::new(Sphere {
+        center: Vec3(0.0, -1000.0, 0.0),
+        radius: 1000.0,
+        material: floor
+    }));
+
+    let spacing = 2.2;
+    let offset = -0.5 * spacing * (materials.len() - 1) as f64;
+    for (i, material) in materials.into_iter().enumerate() {
+        world.add(Box::new(Sphere {
+            center: Vec3(offset + spacing * i as f64, 1.0, 0.0),
+            radius: 1.0,
+            material
+        }));
+    }
+
+    let light = DiffuseLight { 
+        emit: Arc::new(SolidColor::from(Vec3(6.0, 6.0, 6.0)))
+    };
+    let light_rect = Arc::new(XZ::new(light, -6.0, 6.0, -2.0, 2.0, 6.0));
+    world.add(Box::new(light_rect.clone()));
+    let lights = hittable_list!(Box::new(light_rect));
+
+    pub const ASPECT_RATIO: f64 = 16.0 / 9.0;
+    const IMAGE_WIDTH: u32 = 400;
+    const IMAGE_HEIGHT: u32 = (IMAGE_WIDTH as f64 / ASPECT_RATIO) as u32;
+
+    let look_from = Vec3(0.0, 4.0, 18.0);
+    let look_at = Vec3(0.0, 1.0, 0.0);
+    let focus_dist = 10.0;
+    let camera = Camera::custom(
+        look_from,
+        look_at,
+        Vec3(0.0, 1.0, 0.0), 
+        ASPECT_RATIO, 
+        35.0,
+        0.0,
+        focus_dist,
+        0.0,
+        0.0
+    );
+
+    Scene { 
+        camera, 
+        world, 
+        lights,
+        delta_lights: Vec::new(),
+        aspect_ratio: ASPECT_RATIO, 
+        height: IMAGE_HEIGHT, 
+        width: IMAGE_WIDTH, 
+        samples_per_pixel,
+        background: Background::VerticalGradient { bottom: Vec3(0.05, 0.07, 0.1), top: Vec3(0.1, 0.1, 0.1) }
+    }
 }
\ No newline at end of file
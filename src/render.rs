@@ -0,0 +1,381 @@
+use std::{
+    fs::File, 
+    io::{
+        self, 
+        Write
+    }, 
+    path::Path, 
+    sync::{
+        mpsc::{
+            self, 
+            Sender, 
+            Receiver
+        }, 
+        Arc, 
+        Mutex
+    }, 
+    thread::{
+        self, 
+        ScopedJoinHandle, 
+        sleep
+    }, 
+    time::Duration
+};
+
+use clap::clap_derive::ArgEnum;
+use rand::random;
+
+use crate::{
+    scene::Scene, 
+    integrators::{
+        Integrator, 
+        PathTracer
+    }, 
+    types::{
+        vec3::Vec3, 
+        color::Color, 
+        ray::Ray
+    }
+};
+
+/// How work is split between threads when rendering with multiple threads
+#[derive(Debug, Clone, Copy, ArgEnum, PartialEq)]
+pub enum RenderStrategy {
+    /// Every thread renders the whole image with a fraction of the samples
+    ProgressiveAverage,
+    /// The image is split into tiles, each rendered by one thread with all of the samples
+    TileFull,
+    /// The image is split into tiles, and every thread renders each tile with a fraction of the samples
+    TileAverage
+}
+
+/// Settings for `render` which aren't part of the scene
+/// # Fields
+/// `multithreaded` - render with a thread per core, rather than on the calling thread
+/// 
+/// `render_strategy` - how work is split between threads, if `multithreaded`
+/// 
+/// `tile_size` - width and height of tiles, for the tiled render strategies
+/// 
+/// `progress` - called every so often with the fraction of the image rendered so far, from 0 to 1.
+/// Nothing is reported if it's None
+#[derive(Debug, Clone, Copy)]
+pub struct RenderSettings {
+    pub multithreaded: bool,
+    pub render_strategy: RenderStrategy,
+    pub tile_size: u32,
+    pub progress: Option<fn(f64)>
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings { 
+            multithreaded: false, 
+            render_strategy: RenderStrategy::TileAverage, 
+            tile_size: 64, 
+            progress: None 
+        }
+    }
+}
+
+/// A rendered image, in linear color
+/// # Fields
+/// `pixels` - `width * height` colors, row by row starting from the top left
+#[derive(Debug, Clone)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<Color>
+}
+
+impl Image {
+    /// Gamma corrects (with gamma 2) and quantizes a color for 8-bit output
+    fn to_rgb8(pixel: Color) -> [u8; 3] {
+        [(pixel.0.sqrt().clamp(0.0, 1.0) * 256.0) as u8, 
+         (pixel.1.sqrt().clamp(0.0, 1.0) * 256.0) as u8, 
+         (pixel.2.sqrt().clamp(0.0, 1.0) * 256.0) as u8]
+    }
+
+    pub fn write_ppm(&self, mut output: impl Write) -> io::Result<()> {
+        writeln!(output, "P3")?;
+        writeln!(output, "{} {}", self.width, self.height)?;
+        writeln!(output, "255")?;
+        for pixel in self.pixels.iter() {
+            let [r, g, b] = Image::to_rgb8(*pixel);
+            writeln!(output, "{} {} {}", r, g, b)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn save_png(&self, output: &Path) -> image::ImageResult<()> {
+        let buf: Vec<u8> = self.pixels.iter().flat_map(|x| Image::to_rgb8(*x)).collect();
+        image::save_buffer_with_format(
+            output, 
+            buf.as_slice(),
+            self.width, 
+            self.height, 
+            image::ColorType::Rgb8, 
+            image::ImageFormat::Png
+        )
+    }
+
+    /// Saves the image as a PNG or PPM, depending on the extension of `output` (PPM if it's neither)
+    pub fn save(&self, output: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        match output.extension().and_then(|extension| extension.to_str()) {
+            Some("png") => self.save_png(output)?,
+            _ => self.write_ppm(File::create(output)?)?
+        }
+        Ok(())
+    }
+}
+
+/// Renders `scene` with the default path tracer, either on the calling thread or on a thread per core depending on `settings`
+pub fn render(scene: &Scene, settings: &RenderSettings) -> Image {
+    render_with_integrator(scene, &PathTracer::default(), settings)
+}
+
+/// Renders `scene` like `render`, working out the color of each camera ray with `integrator`
+pub fn render_with_integrator(scene: &Scene, integrator: &dyn Integrator, settings: &RenderSettings) -> Image {
+    let pixels = if settings.multithreaded {
+        render_multithreaded(scene, integrator, settings)
+    }
+    else {
+        render_single_threaded(scene, integrator, settings.progress)
+    };
+    Image { width: scene.width, height: scene.height, pixels }
+}
+
+fn render_single_threaded(scene: &Scene, integrator: &dyn Integrator, progress: Option<fn(f64)>) -> Vec<Color> {
+    let mut color_data: Vec<Color> = Vec::with_capacity((scene.width * scene.height) as usize);
+    
+    for j in (0..scene.height).rev() {
+        if let Some(progress) = progress {
+            progress((scene.height - 1 - j) as f64 / scene.height as f64);
+        }
+        for i in 0..scene.width {            
+            let mut color: Color = Vec3(0.0, 0.0, 0.0);
+            #[cfg(feature="ray_debug")]
+            {
+                println!("{} {}", i, j);
+            }
+            for _s in 0..scene.samples_per_pixel {
+                let u = (random::<f64>() + i as f64) / (scene.width - 1) as f64;
+                let v = (random::<f64>() + j as f64) / (scene.height - 1) as f64;
+                let ray: Ray = scene.camera.get_ray(u, v);
+                color += integrator.ray_color(ray, scene) / scene.samples_per_pixel.into();
+            }
+            color_data.push(color);
+        }
+    }
+
+    if let Some(progress) = progress {
+        progress(1.0);
+    }
+    
+    color_data
+}
+
+fn async_render(scene: &Scene, integrator: &dyn Integrator, job: RenderJobMessage, transmit_progress: &Sender<RenderResultMessage>) {
+    let mut scanline: Vec<(Pixel, Color)> = Vec::new();
+    let RenderJobMessage { top_right, bottom_left, samples_per_pixel } = job;
+    for j in (bottom_left.y..top_right.y).rev() {
+        for i in bottom_left.x..top_right.x {            
+            let mut color: Color = Vec3(0.0, 0.0, 0.0);
+            
+            for _s in 0..samples_per_pixel {
+                let u = (random::<f64>() + i as f64) / (scene.width - 1) as f64;
+                let v = (random::<f64>() + j as f64) / (scene.height - 1) as f64;
+                let ray: Ray = scene.camera.get_ray(u, v);
+                color += integrator.ray_color(ray, scene) / job.samples_per_pixel.into();
+            }
+            scanline.push((Pixel { x: i, y: j }, color));
+        }     
+
+        // transmit at end of each scanline
+        transmit_progress.send(RenderResultMessage::Result { rendered_pixels: scanline })
+                            .expect("unable to send data to coordinating thread");
+        scanline = Vec::new();
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Pixel {
+    x: u32,
+    y: u32
+}
+enum RenderResultMessage {
+    Result {
+        rendered_pixels: Vec<(Pixel, Vec3)>
+    },
+    Done
+}
+
+#[derive(Clone, Copy)]
+struct RenderJobMessage {
+    top_right: Pixel,
+    bottom_left: Pixel,
+    samples_per_pixel: u32
+}
+
+struct RenderThread<'scope> {
+    handle: ScopedJoinHandle<'scope, ()>,
+    send_job: Sender<RenderJobMessage>,
+    receive_result: Receiver<RenderResultMessage>,
+    send_done: Sender<()>
+}
+
+/// Splits the image into jobs according to `settings`, and renders them on a thread per core
+fn render_multithreaded(scene: &Scene, integrator: &dyn Integrator, settings: &RenderSettings) -> Vec<Color> {
+    let mut color_data = vec![Vec3(0.0, 0.0, 0.0); (scene.width * scene.height) as usize];
+
+    let cores = num_cpus::get() as u32;
+
+    let RenderSettings { 
+        render_strategy,
+        tile_size, 
+        progress, 
+        .. 
+    } = *settings;
+
+    let horizontal_tiles;
+    let vertical_tiles;
+
+    match render_strategy {
+        RenderStrategy::TileFull | RenderStrategy::TileAverage => {
+            horizontal_tiles = scene.width.div_ceil(tile_size); 
+            vertical_tiles = scene.height.div_ceil(tile_size);
+        }
+        RenderStrategy::ProgressiveAverage => {
+            horizontal_tiles = 1;
+            vertical_tiles = 1;
+        }
+    }
+
+    let samples_per_pixel;
+    let jobs_per_tile;
+    match render_strategy {
+        RenderStrategy::ProgressiveAverage | RenderStrategy::TileAverage => {
+            samples_per_pixel = (scene.samples_per_pixel / cores) + 1;
+            jobs_per_tile = cores;
+        },
+        RenderStrategy::TileFull => {
+            samples_per_pixel = scene.samples_per_pixel;
+            jobs_per_tile = 1;
+        }
+    };
+    let mut jobs = Vec::with_capacity((horizontal_tiles * vertical_tiles) as usize);
+    let mut total_scanlines = 0;
+    // create render jobs
+    for j in (0..vertical_tiles).rev() {
+        for i in 0..horizontal_tiles {
+            for _ in 0..jobs_per_tile {
+                let render_job = RenderJobMessage {
+                    top_right: if render_strategy == RenderStrategy::ProgressiveAverage {
+                        Pixel {
+                            x: scene.width,
+                            y: scene.height
+                        }
+                    }
+                    else {
+                        Pixel { 
+                            x: u32::min((i + 1) * tile_size, scene.width), 
+                            y: u32::min((j + 1) * tile_size, scene.height) 
+                        }
+                    },
+                    bottom_left: Pixel { x: i * tile_size, y: j * tile_size },
+                    samples_per_pixel
+                };
+
+                total_scanlines += render_job.top_right.y - render_job.bottom_left.y;
+                jobs.push(render_job);
+            }
+        }
+    }
+
+    // threads are scoped, so that they can borrow the scene
+    thread::scope(|scope| {
+        let mut children: Vec<RenderThread> = Vec::new();
+        // small images may have fewer jobs than there are cores, and a thread without a job has nothing to do
+        for _ in 0..cores.min(jobs.len() as u32) {
+            let shared_scene = scene;
+            let (result_transmit, result_receive) = mpsc::channel();
+            let (job_transmit, job_receive) = mpsc::channel();
+            let (done_transmit, done_receive) = mpsc::channel();
+            let thread = scope.spawn(move || {
+                sleep(Duration::from_millis(500));
+                while let Ok(job_message) = job_receive.try_recv() {
+                    async_render(shared_scene, integrator, job_message, &result_transmit);
+                }
+
+                result_transmit.send(RenderResultMessage::Done).expect("failed to send message back to main thread");
+                done_receive.recv().expect("failed to receive done signal");
+            });
+            children.push(RenderThread { handle: thread, send_job: job_transmit, receive_result: result_receive, send_done: done_transmit });
+        }
+
+        // assign initial jobs to each thread
+        for (i, job) in jobs.iter().enumerate() {
+            children[i % children.len()].send_job.send(*job).expect("failed to assign job");
+        }
+    
+        const POLLING_INTERVAL: u64 = 1; // 1ms polling interval
+        const REPORTING_INTERVAL: u64 = 5000;
+        let mut completed_threads = 0;
+        let completed_scanlines: Arc<Mutex<i32>> = Arc::new(Mutex::new(0));
+        let completed_scanlines_reporter = completed_scanlines.clone();
+
+        let reporter = progress.map(|progress| scope.spawn(
+            move || {
+                loop {
+                    thread::sleep(Duration::from_millis(REPORTING_INTERVAL));
+                    let completed_scanlines = completed_scanlines_reporter.lock().expect("main thread panic'd, exiting");
+                    progress(*completed_scanlines as f64 / total_scanlines as f64);
+                    if *completed_scanlines >= total_scanlines as i32 {
+                        return;
+                    }
+                }
+            }
+        ));
+
+        while completed_threads < children.len() {
+            sleep(Duration::from_millis(POLLING_INTERVAL));
+            for child in children.iter() {
+                match child.receive_result.try_recv() {
+                    Ok(render_result) => {
+                        match render_result { 
+                            RenderResultMessage::Result { rendered_pixels } => {
+                                for rendered_pixel in rendered_pixels {
+                                    let index = (scene.height - 1 - rendered_pixel.0.y) * scene.width + rendered_pixel.0.x;
+                                    color_data[index as usize] += rendered_pixel.1 / jobs_per_tile as f64;   
+                                }
+                                if let Ok(mut completed_jobs) = completed_scanlines.lock() {
+                                    *completed_jobs += 1;
+                                }
+                            },
+                            RenderResultMessage::Done => {
+                                completed_threads += 1;
+                            }
+                        }
+                    },
+                    Err(_) => {
+                        continue;
+                    },
+                }
+            }
+        
+        }
+    
+        // join up to ensure all threads are finished
+        for child in children {
+            child.send_done.send(()).expect("failed to send done message to child thread");
+            child.handle.join().expect("failed to join child thread");
+        }
+
+        if let Some(reporter) = reporter {
+            reporter.join().expect("failed to join reporter thread");
+        }
+    });
+
+    color_data
+}
\ No newline at end of file
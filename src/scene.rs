@@ -1,11 +1,94 @@
-use std::{io::{Write}, path::Path};
+use rand::random;
+
+use crate::{
+    hittables::{
+        hittable::Hit, 
+        hittable_list::HittableList
+    }, 
+    camera::Camera, 
+    light::Light, 
+    types::{
+        color::Color, 
+        ray::Ray, 
+        vec3::{
+            Point, 
+            Vec3
+        }, 
+        environment_map::EnvironmentMap, 
+        sky::PhysicalSky
+    }
+};
+
+#[derive(Debug)]
+pub enum Background {
+    SolidColor(Color),
+    VerticalGradient {
+        bottom: Color,
+        top: Color
+    },
+    /// Image based lighting, which is also sampled directly as a light
+    EnvironmentMap(Box<EnvironmentMap>),
+    /// Analytic daylight, with the sun disk sampled as a light
+    PhysicalSky(Box<PhysicalSky>)
+}
+
+impl Background {
+    pub fn get_color(&self, r: Ray) -> Color {
+        match self {
+            Background::SolidColor(color) => {
+                *color
+            }
+            Background::VerticalGradient { bottom, top } => {
+                let unit_direction: Vec3 = Vec3::normalized(r.direction);
+                let t = 0.5 * (unit_direction.y() + 1.0);
+                (1.0 - t) * (*top) + t * (*bottom)
+            }
+            Background::EnvironmentMap(map) => {
+                map.radiance(r.direction)
+            }
+            Background::PhysicalSky(sky) => {
+                sky.radiance(r.direction)
+            }
+        }
+    }
+
+    /// Whether the background is sampled as a light, alongside `Scene::lights`
+    pub fn is_sampled(&self) -> bool {
+        match self {
+            Background::EnvironmentMap(_) => true,
+            Background::PhysicalSky(sky) => sky.has_sun(),
+            Background::SolidColor(_) | Background::VerticalGradient { .. } => false
+        }
+    }
+
+    /// Random direction towards the parts of the background that are sampled, see `is_sampled`
+    fn random(&self) -> Option<Vec3> {
+        match self {
+            Background::EnvironmentMap(map) => Some(map.random()),
+            Background::PhysicalSky(sky) if sky.has_sun() => Some(sky.random()),
+            _ => None
+        }
+    }
+
+    /// Probability density (with respect to solid angle) of `random` returning `direction`
+    fn pdf_value(&self, direction: Vec3) -> f64 {
+        match self {
+            Background::EnvironmentMap(map) => map.pdf_value(direction),
+            Background::PhysicalSky(sky) => sky.pdf_value(direction),
+            Background::SolidColor(_) | Background::VerticalGradient { .. } => 0.0
+        }
+    }
+}
 
-use crate::{hittables::hittable_list::HittableList, camera::Camera, types::color::Color, Background};
 
 #[derive(Debug)]
 pub struct Scene {
     pub camera: Camera,
     pub world: HittableList,
+    /// Emissive objects which are sampled directly, in addition to being in `world`
+    pub lights: HittableList,
+    /// Point, spot and directional lights, which aren't part of `world` and are only found by sampling them
+    pub delta_lights: Vec<Light>,
     pub aspect_ratio: f64,
     pub height: u32,
     pub width: u32,
@@ -14,33 +97,34 @@ pub struct Scene {
 }
 
 impl Scene {
-    pub fn print_ppm(&self, color_data: &Vec<Color>, mut output: impl Write) -> Result<(), Box<dyn std::error::Error>> {
-        writeln!(output, "P3")?;
-        writeln!(output, "{} {}", self.width, self.height)?;
-        writeln!(output, "255")?;
-        for pixel in color_data.iter() {
-            writeln!(output, "{} {} {}", 
-                    (pixel.0.sqrt().clamp(0.0, 1.0) * 256.0) as i32, 
-                    (pixel.1.sqrt().clamp(0.0, 1.0) * 256.0) as i32,
-                    (pixel.2.sqrt().clamp(0.0, 1.0) * 256.0) as i32)?;
+    /// Chance of sampling the background rather than one of `lights`, when both can be sampled
+    const BACKGROUND_SAMPLING_WEIGHT: f64 = 0.5;
+
+    /// Chance of `random_light_direction` picking the background
+    fn background_weight(&self) -> f64 {
+        match (self.background.is_sampled(), self.lights.is_empty()) {
+            (false, _) => 0.0,
+            (true, true) => 1.0,
+            (true, false) => Scene::BACKGROUND_SAMPLING_WEIGHT
         }
+    }
+
+    /// Returns a random direction from `origin` towards either one of the lights, or the background if
+    /// it's sampled. None if there's nothing to sample
+    pub fn random_light_direction(&self, origin: Point) -> Option<Vec3> {
+        let weight = self.background_weight();
+        if weight > 0.0 && random::<f64>() < weight {
+            if let Some(direction) = self.background.random() {
+                return Some(direction);
+            }
+        }
+        if self.lights.is_empty() { None } else { Some(self.lights.random(origin)) }
+    }
 
-        Ok(())
-    }
-
-    pub fn save_png(&self, color_data: &Vec<Color>, output: &Path) {
-        let buf: Vec<u8> = color_data.iter().flat_map(|x| {
-            [(x.0.sqrt().clamp(0.0, 1.0) * 256.0) as u8, 
-             (x.1.sqrt().clamp(0.0, 1.0) * 256.0) as u8, 
-             (x.2.sqrt().clamp(0.0, 1.0) * 256.0) as u8]
-        }).collect();
-        image::save_buffer_with_format(
-            output, 
-            buf.as_slice(),
-            self.width, 
-            self.height, 
-            image::ColorType::Rgb8, 
-            image::ImageFormat::Png
-        ).expect("failed to write png");
+    /// Probability density (with respect to solid angle) of `random_light_direction` returning `direction`
+    pub fn light_pdf(&self, origin: Point, direction: Vec3) -> f64 {
+        let weight = self.background_weight();
+        let lights = if self.lights.is_empty() { 0.0 } else { self.lights.pdf_value(origin, direction) };
+        (1.0 - weight) * lights + weight * self.background.pdf_value(direction)
     }
 }
\ No newline at end of file
@@ -0,0 +1,499 @@
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+use toml::Spanned;
+
+use crate::{
+    hittables::mesh::DEFAULT_SMOOTHING_ANGLE,
+    types::bvh::SplitStrategy
+};
+
+// The layout of a scene file, as it's deserialized. These mirror the types they
+// describe, but refer to textures and materials by name rather than holding them
+
+/// Top level of a scene file
+/// # Fields
+/// `textures`, `materials` - named textures and materials, which are referred to by name
+/// from materials and objects respectively
+///
+/// `objects` - everything in the world. Objects with an emissive material are also sampled as lights
+///
+/// `lights` - point, spot and directional lights, which light the scene without being visible in it
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SceneDescription {
+    pub image: ImageDescription,
+    pub camera: CameraDescription,
+    #[serde(default)]
+    pub background: BackgroundDescription,
+    #[serde(default)]
+    pub acceleration: AccelerationDescription,
+    #[serde(default)]
+    pub textures: BTreeMap<String, Spanned<TextureDescription>>,
+    #[serde(default)]
+    pub materials: BTreeMap<String, Spanned<MaterialDescription>>,
+    #[serde(default)]
+    pub objects: Vec<Spanned<ObjectDescription>>,
+    #[serde(default)]
+    pub lights: Vec<LightDescription>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ImageDescription {
+    pub width: u32,
+    pub height: u32
+}
+
+/// How the BVH over the objects is built
+/// # Fields
+/// `print_statistics` - print the shape of the BVH once it's built, see `BVHStatistics`
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct AccelerationDescription {
+    #[serde(default)]
+    pub split: SplitStrategy,
+    #[serde(default)]
+    pub print_statistics: bool
+}
+
+fn default_focus_distance() -> f64 {
+    10.0
+}
+
+/// Where the camera is. `look_from`, `look_at` and `vfov` are required, unless the camera is taken
+/// from a glTF file with `gltf` - then any of them that are given override the glTF camera's
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CameraDescription {
+    pub look_from: Option<[f64; 3]>,
+    pub look_at: Option<[f64; 3]>,
+    /// Defaults to +y, or the glTF camera's up direction
+    pub up: Option<[f64; 3]>,
+    /// Vertical field of view, in degrees
+    pub vfov: Option<f64>,
+    #[serde(default)]
+    pub aperture: f64,
+    #[serde(default = "default_focus_distance")]
+    pub focus_distance: f64,
+    /// Times the shutter opens and closes, for motion blur
+    #[serde(default)]
+    pub shutter: [f64; 2],
+    pub gltf: Option<GltfCameraDescription>
+}
+
+/// A camera from a glTF file. `path` is relative to the scene file, and `camera` counts the
+/// cameras in the file's default scene, starting from 0
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GltfCameraDescription {
+    pub path: String,
+    #[serde(default)]
+    pub camera: usize
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum BackgroundDescription {
+    Solid {
+        color: [f64; 3]
+    },
+    Gradient {
+        top: [f64; 3],
+        bottom: [f64; 3]
+    },
+    /// An equirectangular .hdr or .exr image (relative to the scene file) lighting the scene, turned
+    /// `rotation` degrees about the y axis and scaled by `intensity`
+    EnvironmentMap {
+        path: String,
+        #[serde(default)]
+        rotation: f64,
+        #[serde(default = "default_intensity")]
+        intensity: f64
+    },
+    /// Analytic clear daylight sky with a sun disk, see `PhysicalSky`. `turbidity` is how hazy the air is,
+    /// from 2 (very clear) to around 10
+    Sky {
+        sun: SunDescription,
+        #[serde(default = "default_turbidity")]
+        turbidity: f64,
+        #[serde(default = "default_intensity")]
+        intensity: f64
+    }
+}
+
+fn default_intensity() -> f64 {
+    1.0
+}
+
+fn default_turbidity() -> f64 {
+    3.0
+}
+
+/// Where the sun of a `sky` background is, either a direction towards it, or a place and time on earth.
+/// For the latter, -z is north and +x east
+/// # Fields
+/// `latitude`, `longitude` - in degrees, north and east are positive
+///
+/// `day` - day of the year, 1 being January 1st
+///
+/// `time` - local time in hours (e.g 13.5 for half past one), on a clock `timezone` hours ahead of UTC
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum SunDescription {
+    Direction {
+        direction: [f64; 3]
+    },
+    Location {
+        latitude: f64,
+        longitude: f64,
+        day: f64,
+        time: f64,
+        #[serde(default)]
+        timezone: f64
+    }
+}
+
+impl Default for BackgroundDescription {
+    fn default() -> Self {
+        BackgroundDescription::Solid { color: [0.0, 0.0, 0.0] }
+    }
+}
+
+/// A light which isn't an object. Its color is `color` scaled by `intensity`, which is the radiant intensity of
+/// point and spot lights, and the irradiance of directional lights
+#[derive(Deserialize)]
+pub struct LightDescription {
+    #[serde(flatten)]
+    pub kind: LightKind,
+    #[serde(default = "default_light_color")]
+    pub color: [f64; 3],
+    #[serde(default = "default_intensity")]
+    pub intensity: f64
+}
+
+fn default_light_color() -> [f64; 3] {
+    [1.0, 1.0, 1.0]
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LightKind {
+    Point {
+        position: [f64; 3]
+    },
+    /// Shines along `direction`, at full strength out to `inner_angle` degrees from it and
+    /// falling off to nothing at `outer_angle` degrees
+    Spot {
+        position: [f64; 3],
+        direction: [f64; 3],
+        #[serde(default)]
+        inner_angle: f64,
+        outer_angle: f64
+    },
+    /// `direction` is the way the light travels
+    Directional {
+        direction: [f64; 3]
+    }
+}
+
+/// A texture input of a material, which is either a constant color, a constant grey value,
+/// or the name of a texture in the `textures` table
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+pub enum TextureInput {
+    Color([f64; 3]),
+    Value(f64),
+    Named(String)
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum TextureDescription {
+    Solid {
+        color: [f64; 3]
+    },
+    Checker {
+        odd: [f64; 3],
+        even: [f64; 3]
+    },
+    Noise {
+        scale: f64
+    },
+    /// `path` is relative to the scene file
+    Image {
+        path: String
+    },
+    /// Colors interpolated from the vertices of a mesh, with `fallback` used for anything without them
+    VertexColor {
+        #[serde(default = "default_vertex_color_fallback")]
+        fallback: [f64; 3]
+    }
+}
+
+fn default_vertex_color_fallback() -> [f64; 3] {
+    [0.8, 0.8, 0.8]
+}
+
+/// Index of refraction of a `conductor`, either the name of a preset metal or explicit values
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum ConductorIor {
+    Named(String),
+    Complex {
+        eta: [f64; 3],
+        k: [f64; 3]
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AbsorptionDescription {
+    pub color: [f64; 3],
+    pub distance: f64
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NormalMapDescription {
+    pub texture: TextureInput,
+    #[serde(default = "default_strength")]
+    pub strength: f64
+}
+
+fn default_strength() -> f64 {
+    1.0
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BumpMapDescription {
+    pub texture: TextureInput,
+    pub scale: f64
+}
+
+/// A material, along with the optional normal or bump map it's shaded with
+#[derive(Deserialize)]
+pub struct MaterialDescription {
+    #[serde(flatten)]
+    pub kind: MaterialKind,
+    pub normal_map: Option<NormalMapDescription>,
+    pub bump_map: Option<BumpMapDescription>
+}
+
+fn default_ior() -> f64 {
+    1.5
+}
+
+/// The parameters of a `principled` material, every one of them is optional
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PrincipledDescription {
+    pub base_color: TextureInput,
+    pub metallic: Option<TextureInput>,
+    pub roughness: Option<TextureInput>,
+    pub specular: Option<TextureInput>,
+    pub specular_tint: Option<TextureInput>,
+    pub sheen: Option<TextureInput>,
+    pub clearcoat: Option<TextureInput>,
+    pub transmission: Option<TextureInput>,
+    pub index_of_refraction: Option<f64>
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MaterialKind {
+    Lambertian {
+        albedo: TextureInput
+    },
+    Metal {
+        albedo: [f64; 3],
+        #[serde(default)]
+        fuzz: f64
+    },
+    Dielectric {
+        #[serde(default = "default_ior")]
+        index_of_refraction: f64,
+        absorption: Option<AbsorptionDescription>
+    },
+    RoughDielectric {
+        #[serde(default = "default_ior")]
+        index_of_refraction: f64,
+        roughness: TextureInput,
+        absorption: Option<AbsorptionDescription>
+    },
+    Conductor {
+        tint: Option<TextureInput>,
+        roughness: TextureInput,
+        ior: ConductorIor
+    },
+    Principled(PrincipledDescription),
+    DiffuseLight {
+        emit: TextureInput
+    },
+    Isotropic {
+        albedo: TextureInput
+    },
+    /// `first` and `second` are names of other materials
+    Mix {
+        first: String,
+        second: String,
+        factor: TextureInput
+    }
+}
+
+/// Which plane an axis-aligned rectangle lies in
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Plane {
+    Xy,
+    Xz,
+    Yz
+}
+
+/// Scale factor of a `scale` transform step, either uniform or per axis
+#[derive(Deserialize, Clone, Copy)]
+#[serde(untagged)]
+pub enum ScaleFactor {
+    Uniform(f64),
+    PerAxis([f64; 3])
+}
+
+/// One step of an object's transform. Steps are applied in the order they're listed
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum TransformStep {
+    Translate {
+        offset: [f64; 3]
+    },
+    Rotate {
+        axis: [f64; 3],
+        degrees: f64
+    },
+    Scale {
+        factor: ScaleFactor
+    },
+    /// e.g `xy` is how far x moves for each unit of y
+    Shear {
+        #[serde(default)]
+        xy: f64,
+        #[serde(default)]
+        xz: f64,
+        #[serde(default)]
+        yx: f64,
+        #[serde(default)]
+        yz: f64,
+        #[serde(default)]
+        zx: f64,
+        #[serde(default)]
+        zy: f64
+    },
+    /// An arbitrary affine transform, given as the rows of a 4x4 matrix
+    Matrix {
+        rows: [[f64; 4]; 4]
+    }
+}
+
+/// Where an object is at `time`, for motion blur. `transform` is applied after the object's own `transform`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KeyframeDescription {
+    pub time: f64,
+    #[serde(default)]
+    pub transform: Vec<TransformStep>
+}
+
+/// Fills an object with a participating medium (fog, smoke, etc.) of the given density
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MediumDescription {
+    pub density: f64,
+    pub albedo: TextureInput
+}
+
+/// An object in the world
+/// # Fields
+/// `name` - used in error messages, objects without one are referred to by their position in the file
+///
+/// `material` - name of the material, this can be left out for meshes to use the materials of the file
+///
+/// `transform` - steps which move the object into place, see `TransformStep`
+///
+/// `keyframes` - if present, the object moves between them over the time the shutter is open
+///
+/// `medium` - if present, the object is the boundary of a medium instead of a surface,
+/// and `material` isn't used
+#[derive(Deserialize)]
+pub struct ObjectDescription {
+    pub name: Option<String>,
+    #[serde(flatten)]
+    pub kind: ObjectKind,
+    pub material: Option<String>,
+    #[serde(default)]
+    pub transform: Vec<TransformStep>,
+    #[serde(default)]
+    pub keyframes: Vec<KeyframeDescription>,
+    pub medium: Option<MediumDescription>
+}
+
+fn default_smoothing_angle() -> f64 {
+    DEFAULT_SMOOTHING_ANGLE
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ObjectKind {
+    Sphere {
+        center: [f64; 3],
+        radius: f64
+    },
+    MovingSphere {
+        start_center: [f64; 3],
+        end_center: [f64; 3],
+        start_time: f64,
+        end_time: f64,
+        radius: f64
+    },
+    /// `a` and `b` are the bounds along the first and second axis of the plane, and
+    /// `k` the position along the third
+    Rect {
+        plane: Plane,
+        a: [f64; 2],
+        b: [f64; 2],
+        k: f64
+    },
+    Block {
+        min: [f64; 3],
+        max: [f64; 3]
+    },
+    Triangle {
+        vertices: [[f64; 3]; 3]
+    },
+    /// `path` (a .obj or .ply file) is relative to the scene file. Every object with the same `path` (and `smoothing_angle`) shares
+    /// a single copy of the mesh. `smoothing_angle` is used for faces without normals in the file, see
+    /// `Mesh::from_file_with_smoothing`. Triangles with an emissive material (from the file, or `material`) are sampled as lights
+    Mesh {
+        path: String,
+        #[serde(default = "default_smoothing_angle")]
+        smoothing_angle: f64
+    },
+    /// Every mesh in the default scene of a .gltf or .glb file (relative to the scene file), placed by its nodes.
+    /// `material`, if given, replaces the file's materials. Triangles with an emissive material are sampled as lights
+    Gltf {
+        path: String
+    }
+}
+
+impl ObjectKind {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            ObjectKind::Sphere { .. } => "sphere",
+            ObjectKind::MovingSphere { .. } => "moving_sphere",
+            ObjectKind::Rect { .. } => "rect",
+            ObjectKind::Block { .. } => "block",
+            ObjectKind::Triangle { .. } => "triangle",
+            ObjectKind::Mesh { .. } => "mesh",
+            ObjectKind::Gltf { .. } => "gltf"
+        }
+    }
+}
\ No newline at end of file
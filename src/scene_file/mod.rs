@@ -0,0 +1,786 @@
+use std::{
+    collections::{
+        BTreeMap,
+        HashMap
+    },
+    fmt::Display,
+    fs,
+    io,
+    ops::Range,
+    path::{
+        Path,
+        PathBuf
+    },
+    sync::Arc
+};
+
+use image::ImageError;
+use serde::Deserialize;
+use toml::Spanned;
+
+use crate::{
+    camera::Camera,
+    light::Light,
+    gltf_scene::{
+        GltfScene,
+        GltfError
+    },
+    scene::{
+        Scene,
+        Background
+    },
+    hittables::{
+        hittable::Hit,
+        hittable_list::HittableList,
+        sphere::Sphere,
+        moving_sphere::MovingSphere,
+        aarect::{
+            XY,
+            XZ,
+            YZ
+        },
+        block::Block,
+        tri::Triangle,
+        mesh::{
+            Mesh,
+            MeshError
+        },
+        instance::Instance,
+        constant_medium::ConstantMedium
+    },
+    types::{
+        vec3::Vec3,
+        bvh::{
+            BVH,
+            SplitStrategy
+        },
+        environment_map::EnvironmentMap,
+        sky::PhysicalSky,
+        transform::{
+            Transform,
+            TransformData,
+            AnimatedTransform
+        },
+        texture::{
+            Texture,
+            SolidColor,
+            CheckerTexture,
+            NoiseTexture,
+            ImageTexture,
+            VertexColorTexture
+        },
+        materials::{
+            Material,
+            MixFactor,
+            NormalPerturbation,
+            Absorption,
+            microfacet::ComplexIor,
+            principled::PrincipledBsdf
+        }
+    },
+    utils::degrees_to_radians
+};
+
+use self::description::{
+    SceneDescription,
+    BackgroundDescription,
+    SunDescription,
+    LightKind,
+    TextureDescription,
+    TextureInput,
+    MaterialDescription,
+    MaterialKind,
+    AbsorptionDescription,
+    ConductorIor,
+    ObjectDescription,
+    ObjectKind,
+    Plane,
+    ScaleFactor,
+    TransformStep
+};
+
+pub mod description;
+
+/// What went wrong while loading a scene file
+#[derive(Debug)]
+pub enum SceneFileErrorKind {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    UnknownTexture(String),
+    UnknownMaterial(String),
+    /// A `mix` material which (eventually) contains itself
+    MaterialCycle(String),
+    UnknownMetal(String),
+    /// An absorption `distance` which isn't greater than 0
+    AbsorptionDistance(f64),
+    /// Objects other than meshes and media need a material
+    MissingMaterial,
+    /// A camera setting which isn't given, and can't be taken from a glTF camera
+    MissingCameraSetting(&'static str),
+    /// The glTF file doesn't have as many cameras as the index asked for
+    UnknownGltfCamera(usize),
+    /// A transform step which flattens the object, so it can't be inverted
+    SingularTransform,
+    /// Neighbouring keyframes where only one is mirrored, which would flatten the object between them
+    MirroredKeyframes,
+    Texture(ImageError),
+    Mesh(MeshError),
+    Gltf(GltfError)
+}
+
+/// An error in a scene file, along with where it happened
+/// # Fields
+/// `line` - line of the scene file the offending object starts on, if known
+///
+/// `object` - description of the offending texture, material or object, e.g "material 'glass'"
+#[derive(Debug)]
+pub struct SceneFileError {
+    pub path: PathBuf,
+    pub line: Option<usize>,
+    pub object: Option<String>,
+    pub kind: Box<SceneFileErrorKind>
+}
+
+impl Display for SceneFileErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SceneFileErrorKind::Io(error) => write!(f, "unable to read file: {}", error),
+            SceneFileErrorKind::Parse(error) => write!(f, "{}", error.message()),
+            SceneFileErrorKind::UnknownTexture(name) => write!(f, "no texture named '{}'", name),
+            SceneFileErrorKind::UnknownMaterial(name) => write!(f, "no material named '{}'", name),
+            SceneFileErrorKind::MaterialCycle(name) => write!(f, "material '{}' contains itself", name),
+            SceneFileErrorKind::UnknownMetal(name) => {
+                write!(f, "unknown metal '{}', expected 'gold', 'copper' or explicit `eta` and `k`", name)
+            },
+            SceneFileErrorKind::AbsorptionDistance(distance) => {
+                write!(f, "absorption `distance` must be greater than 0, not {}", distance)
+            },
+            SceneFileErrorKind::MissingMaterial => write!(f, "missing `material`"),
+            SceneFileErrorKind::MissingCameraSetting(name) => write!(f, "missing `{}`", name),
+            SceneFileErrorKind::UnknownGltfCamera(index) => write!(f, "the glTF file has no camera {}", index),
+            SceneFileErrorKind::SingularTransform => write!(f, "transform can't be inverted"),
+            SceneFileErrorKind::MirroredKeyframes => {
+                write!(f, "keyframes can't switch between mirrored and not, the object would be flattened in between")
+            },
+            SceneFileErrorKind::Texture(error) => write!(f, "unable to load image: {}", error),
+            SceneFileErrorKind::Mesh(error) => write!(f, "unable to load mesh: {}", error),
+            SceneFileErrorKind::Gltf(error) => write!(f, "unable to load glTF file: {}", error)
+        }
+    }
+}
+
+impl Display for SceneFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.path.display())?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+        }
+        if let Some(object) = &self.object {
+            write!(f, " ({})", object)?;
+        }
+        write!(f, ": {}", self.kind)
+    }
+}
+
+impl std::error::Error for SceneFileError {}
+
+/// Loads a scene from a TOML scene file. See `description::SceneDescription` for the layout of the file;
+/// paths to images and meshes in it are relative to the file. `split` overrides the file's `acceleration.split`
+pub fn load(path: &Path, samples_per_pixel: u32, split: Option<SplitStrategy>) -> Result<Scene, SceneFileError> {
+    let error = |line, kind| SceneFileError { path: path.to_path_buf(), line, object: None, kind: Box::new(kind) };
+    let source = fs::read_to_string(path).map_err(|e| error(None, SceneFileErrorKind::Io(e)))?;
+    let mut description: SceneDescription = toml::from_str(&source).map_err(|e| {
+        let line = e.span().map(|span| line_number(&source, span.start));
+        let object = e.span().and_then(|span| Outline::find(&source, span.start));
+        SceneFileError { object, ..error(line, SceneFileErrorKind::Parse(e)) }
+    })?;
+    if let Some(split) = split {
+        description.acceleration.split = split;
+    }
+
+    let directory = path.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+    let mut loader = SceneLoader {
+        path,
+        source: &source,
+        directory,
+        description: &description,
+        textures: HashMap::new(),
+        materials: HashMap::new(),
+        meshes: HashMap::new(),
+        gltf_scenes: HashMap::new()
+    };
+    loader.build(samples_per_pixel)
+}
+
+/// Just enough of a scene file to know where its textures, materials and objects are. Used to name 
+/// the object containing an error, when the file couldn't be parsed in full
+#[derive(Deserialize)]
+struct Outline {
+    #[serde(default)]
+    textures: BTreeMap<String, Spanned<toml::Value>>,
+    #[serde(default)]
+    materials: BTreeMap<String, Spanned<toml::Value>>,
+    #[serde(default)]
+    objects: Vec<Spanned<OutlineObject>>
+}
+
+#[derive(Deserialize)]
+struct OutlineObject {
+    name: Option<String>,
+    #[serde(rename = "type")]
+    kind: Option<String>
+}
+
+impl Outline {
+    /// Describes the texture, material or object containing the byte at `offset`, if the file is valid TOML
+    fn find(source: &str, offset: usize) -> Option<String> {
+        let outline: Outline = toml::from_str(source).ok()?;
+        let contains = |span: Range<usize>| span.contains(&offset);
+        if let Some((name, _)) = outline.textures.iter().find(|(_, texture)| contains(texture.span())) {
+            return Some(format!("texture '{}'", name));
+        }
+        if let Some((name, _)) = outline.materials.iter().find(|(_, material)| contains(material.span())) {
+            return Some(format!("material '{}'", name));
+        }
+        let (i, object) = outline.objects.iter().enumerate().find(|(_, object)| contains(object.span()))?;
+        Some(match &object.get_ref().name {
+            Some(name) => format!("object '{}'", name),
+            None => format!("objects[{}] ({})", i, object.get_ref().kind.as_deref().unwrap_or("unknown type"))
+        })
+    }
+}
+
+/// Line (starting from 1) containing the byte at `offset`
+fn line_number(source: &str, offset: usize) -> usize {
+    source[..offset.min(source.len())].matches('\n').count() + 1
+}
+
+fn vec3(value: [f64; 3]) -> Vec3 {
+    Vec3(value[0], value[1], value[2])
+}
+
+/// Applies each of `steps` in turn after `transform`
+fn build_transform(transform: TransformData, steps: &[TransformStep]) -> Result<TransformData, SceneFileErrorKind> {
+    let mut transform = transform;
+    for step in steps {
+        transform = match step {
+            TransformStep::Translate { offset } => transform.translate(vec3(*offset)),
+            TransformStep::Rotate { axis, degrees } => {
+                transform.rotate_angle_axis(vec3(*axis), degrees_to_radians(*degrees))
+            },
+            TransformStep::Scale { factor } => {
+                let factors = match *factor {
+                    ScaleFactor::Uniform(factor) => Vec3(factor, factor, factor),
+                    ScaleFactor::PerAxis(factors) => vec3(factors)
+                };
+                if factors.0 == 0.0 || factors.1 == 0.0 || factors.2 == 0.0 {
+                    return Err(SceneFileErrorKind::SingularTransform);
+                }
+                transform.scale_non_uniform(factors)
+            },
+            TransformStep::Shear { xy, xz, yx, yz, zx, zy } => {
+                transform.shear(*xy, *xz, *yx, *yz, *zx, *zy).ok_or(SceneFileErrorKind::SingularTransform)?
+            },
+            TransformStep::Matrix { rows } => {
+                transform.apply_matrix(*rows).ok_or(SceneFileErrorKind::SingularTransform)?
+            }
+        };
+    }
+    Ok(transform)
+}
+
+/// Builds the objects in a parsed scene file. Textures, materials and meshes are built once,
+/// and shared by everything that refers to them
+struct SceneLoader<'a> {
+    path: &'a Path,
+    source: &'a str,
+    directory: PathBuf,
+    description: &'a SceneDescription,
+    textures: HashMap<String, Arc<dyn Texture>>,
+    materials: HashMap<String, Material>,
+    /// keyed by path and the bits of the smoothing angle
+    meshes: HashMap<(PathBuf, u64), Arc<Mesh>>,
+    gltf_scenes: HashMap<PathBuf, Arc<GltfScene>>
+}
+
+impl<'a> SceneLoader<'a> {
+    fn error(&self, span: Range<usize>, object: String, kind: SceneFileErrorKind) -> SceneFileError {
+        SceneFileError {
+            path: self.path.to_path_buf(),
+            line: Some(line_number(self.source, span.start)),
+            object: Some(object),
+            kind: Box::new(kind)
+        }
+    }
+
+    fn build(&mut self, samples_per_pixel: u32) -> Result<Scene, SceneFileError> {
+        for (name, texture) in &self.description.textures {
+            let built = self.build_texture(texture.get_ref())
+                .map_err(|kind| self.error(texture.span(), format!("texture '{}'", name), kind))?;
+            self.textures.insert(name.clone(), built);
+        }
+        for name in self.description.materials.keys() {
+            self.material(name, &mut Vec::new())?;
+        }
+
+        let camera_description = &self.description.camera;
+        let [time0, time1] = camera_description.shutter;
+        let mut objects = Vec::new();
+        let mut lights = HittableList::new();
+        for (i, object) in self.description.objects.iter().enumerate() {
+            let label = match &object.get_ref().name {
+                Some(name) => format!("object '{}'", name),
+                None => format!("objects[{}] ({})", i, object.get_ref().kind.type_name())
+            };
+            let (built, emission) = self.build_object(object.get_ref())
+                .map_err(|kind| self.error(object.span(), label, kind))?;
+            let built: Box<dyn Hit> = match emission {
+                Emission::None => built,
+                Emission::Whole => {
+                    // lights are sampled separately from the world, so they're in both
+                    let light: Arc<dyn Hit> = Arc::from(built);
+                    lights.add(Box::new(light.clone()));
+                    Box::new(light)
+                },
+                Emission::Parts(parts) => {
+                    lights.add(parts);
+                    built
+                }
+            };
+            objects.push(built);
+        }
+
+        let mut world = HittableList::new();
+        if !objects.is_empty() {
+            let acceleration = &self.description.acceleration;
+            let bvh = BVH::make_with_strategy(objects, time0, time1, acceleration.split);
+            if acceleration.print_statistics {
+                eprintln!("BVH ({:?}) {}", acceleration.split, bvh.statistics());
+            }
+            world.add(Box::new(bvh));
+        }
+
+        let image = &self.description.image;
+        let aspect_ratio = image.width as f64 / image.height as f64;
+        let camera = self.build_camera(aspect_ratio).map_err(|kind| SceneFileError {
+            path: self.path.to_path_buf(),
+            line: None,
+            object: Some("camera".to_string()),
+            kind: Box::new(kind)
+        })?;
+
+        let background = match &self.description.background {
+            BackgroundDescription::Solid { color } => Background::SolidColor(vec3(*color)),
+            // `VerticalGradient` returns its `bottom` color looking straight up
+            BackgroundDescription::Gradient { top, bottom } => {
+                Background::VerticalGradient { bottom: vec3(*top), top: vec3(*bottom) }
+            },
+            BackgroundDescription::EnvironmentMap { path, rotation, intensity } => {
+                let map = EnvironmentMap::open(&self.directory.join(path), *rotation, *intensity).map_err(|error| SceneFileError {
+                    path: self.path.to_path_buf(),
+                    line: None,
+                    object: Some("background".to_string()),
+                    kind: Box::new(SceneFileErrorKind::Texture(error))
+                })?;
+                Background::EnvironmentMap(Box::new(map))
+            },
+            BackgroundDescription::Sky { sun, turbidity, intensity } => {
+                let sun_direction = match sun {
+                    SunDescription::Direction { direction } => vec3(*direction),
+                    SunDescription::Location { latitude, longitude, day, time, timezone } => {
+                        PhysicalSky::sun_direction_at(*latitude, *longitude, *day, *time, *timezone)
+                    }
+                };
+                Background::PhysicalSky(Box::new(PhysicalSky::new(sun_direction, *turbidity, *intensity)))
+            }
+        };
+
+        let delta_lights = self.description.lights.iter().map(|light| {
+            let color = light.intensity * vec3(light.color);
+            match &light.kind {
+                LightKind::Point { position } => Light::point(vec3(*position), color),
+                LightKind::Spot { position, direction, inner_angle, outer_angle } => {
+                    Light::spot(vec3(*position), vec3(*direction), color, *inner_angle, *outer_angle)
+                },
+                LightKind::Directional { direction } => Light::directional(vec3(*direction), color)
+            }
+        }).collect();
+
+        Ok(Scene {
+            camera,
+            world,
+            lights,
+            delta_lights,
+            aspect_ratio,
+            height: image.height,
+            width: image.width,
+            samples_per_pixel,
+            background
+        })
+    }
+
+    fn build_texture(&self, texture: &TextureDescription) -> Result<Arc<dyn Texture>, SceneFileErrorKind> {
+        Ok(match texture {
+            TextureDescription::Solid { color } => Arc::new(SolidColor::from(vec3(*color))),
+            TextureDescription::Checker { odd, even } => {
+                Arc::new(CheckerTexture::make_solid_checkered(vec3(*odd), vec3(*even)))
+            },
+            TextureDescription::Noise { scale } => Arc::new(NoiseTexture::new(*scale)),
+            TextureDescription::Image { path } => {
+                let image = ImageTexture::open(&self.directory.join(path)).map_err(SceneFileErrorKind::Texture)?;
+                Arc::new(image)
+            },
+            TextureDescription::VertexColor { fallback } => Arc::new(VertexColorTexture::new(vec3(*fallback)))
+        })
+    }
+
+    fn texture(&self, input: &TextureInput) -> Result<Arc<dyn Texture>, SceneFileErrorKind> {
+        match input {
+            TextureInput::Color(color) => Ok(Arc::new(SolidColor::from(vec3(*color)))),
+            TextureInput::Value(value) => Ok(Arc::new(SolidColor::from(Vec3(*value, *value, *value)))),
+            TextureInput::Named(name) => {
+                self.textures.get(name).cloned().ok_or_else(|| SceneFileErrorKind::UnknownTexture(name.clone()))
+            }
+        }
+    }
+
+    /// Looks up a material by name, building it (and any materials it's mixed from) if it hasn't been already.
+    /// `stack` holds the names of the materials being built, to catch materials mixed with themselves
+    fn material(&mut self, name: &str, stack: &mut Vec<String>) -> Result<Material, SceneFileError> {
+        if let Some(material) = self.materials.get(name) {
+            return Ok(material.clone());
+        }
+        let description = self.description;
+        let material = match description.materials.get(name) {
+            Some(material) => material,
+            None => {
+                let kind = SceneFileErrorKind::UnknownMaterial(name.to_string());
+                return Err(match stack.last() {
+                    // the error is in the material that refers to this one
+                    Some(parent) => {
+                        let span = description.materials[parent].span();
+                        self.error(span, format!("material '{}'", parent), kind)
+                    },
+                    None => SceneFileError { path: self.path.to_path_buf(), line: None, object: None, kind: Box::new(kind) }
+                });
+            }
+        };
+        let label = format!("material '{}'", name);
+        if stack.iter().any(|x| x == name) {
+            return Err(self.error(material.span(), label, SceneFileErrorKind::MaterialCycle(name.to_string())));
+        }
+
+        stack.push(name.to_string());
+        let built = self.build_material(material, stack)?;
+        stack.pop();
+        // shared, so every object using it shows up as one material in the material id view
+        let built = built.shared();
+        self.materials.insert(name.to_string(), built.clone());
+        Ok(built)
+    }
+
+    fn build_material(&mut self, material: &Spanned<MaterialDescription>, stack: &mut Vec<String>) -> Result<Material, SceneFileError> {
+        let label = format!("material '{}'", stack.last().expect("material being built is on the stack"));
+        let error = |loader: &Self, kind| loader.error(material.span(), label.clone(), kind);
+        let MaterialDescription { kind, normal_map, bump_map } = material.get_ref();
+        let absorption = |absorption: &Option<AbsorptionDescription>| match absorption {
+            // the color is raised to the power of distance travelled over `distance`, which must be positive
+            Some(a) if a.distance.is_nan() || a.distance <= 0.0 => Err(SceneFileErrorKind::AbsorptionDistance(a.distance)),
+            Some(a) => Ok(Some(Absorption { color: vec3(a.color), distance: a.distance })),
+            None => Ok(None)
+        };
+
+        let built = match kind {
+            MaterialKind::Mix { first, second, factor } => {
+                let first = self.material(first, stack)?;
+                let second = self.material(second, stack)?;
+                let factor = match factor {
+                    TextureInput::Value(value) => MixFactor::Constant(*value),
+                    input => MixFactor::Texture(self.texture(input).map_err(|kind| error(self, kind))?)
+                };
+                Material::Mix { first: Box::new(first), second: Box::new(second), factor }
+            },
+            kind => self.build_single_material(kind, absorption).map_err(|kind| error(self, kind))?
+        };
+
+        let perturbation = match (normal_map, bump_map) {
+            (Some(normal_map), _) => Some(NormalPerturbation::NormalMap {
+                texture: self.texture(&normal_map.texture).map_err(|kind| error(self, kind))?,
+                strength: normal_map.strength
+            }),
+            (None, Some(bump_map)) => Some(NormalPerturbation::BumpMap {
+                texture: self.texture(&bump_map.texture).map_err(|kind| error(self, kind))?,
+                scale: bump_map.scale
+            }),
+            (None, None) => None
+        };
+        Ok(match perturbation {
+            Some(perturbation) => Material::Perturbed { material: Box::new(built), perturbation },
+            None => built
+        })
+    }
+
+    /// Builds every kind of material other than `mix`, which refers to other materials
+    fn build_single_material(
+        &self,
+        kind: &MaterialKind,
+        absorption: impl Fn(&Option<AbsorptionDescription>) -> Result<Option<Absorption>, SceneFileErrorKind>
+    ) -> Result<Material, SceneFileErrorKind> {
+        Ok(match kind {
+            MaterialKind::Lambertian { albedo } => Material::Lambertian { albedo: self.texture(albedo)? },
+            MaterialKind::Metal { albedo, fuzz } => Material::Metal { albedo: vec3(*albedo), fuzz: *fuzz },
+            MaterialKind::Dielectric { index_of_refraction, absorption: description } => Material::Dielectric {
+                index_of_refraction: *index_of_refraction,
+                absorption: absorption(description)?
+            },
+            MaterialKind::RoughDielectric { index_of_refraction, roughness, absorption: description } => {
+                Material::RoughDielectric {
+                    index_of_refraction: *index_of_refraction,
+                    roughness: self.texture(roughness)?,
+                    absorption: absorption(description)?
+                }
+            },
+            MaterialKind::Conductor { tint, roughness, ior } => {
+                let ior = match ior {
+                    ConductorIor::Named(name) => match name.as_str() {
+                        "gold" => ComplexIor::GOLD,
+                        "copper" => ComplexIor::COPPER,
+                        _ => return Err(SceneFileErrorKind::UnknownMetal(name.clone()))
+                    },
+                    ConductorIor::Complex { eta, k } => ComplexIor { eta: vec3(*eta), k: vec3(*k) }
+                };
+                Material::Conductor {
+                    tint: self.texture(tint.as_ref().unwrap_or(&TextureInput::Value(1.0)))?,
+                    roughness: self.texture(roughness)?,
+                    ior
+                }
+            },
+            MaterialKind::Principled(description) => {
+                let defaults = PrincipledBsdf::new(self.texture(&description.base_color)?);
+                let parameter = |input: &Option<TextureInput>, default: &Arc<dyn Texture>| {
+                    input.as_ref().map_or(Ok(default.clone()), |input| self.texture(input))
+                };
+                Material::Principled(Box::new(PrincipledBsdf {
+                    metallic: parameter(&description.metallic, &defaults.metallic)?,
+                    roughness: parameter(&description.roughness, &defaults.roughness)?,
+                    specular: parameter(&description.specular, &defaults.specular)?,
+                    specular_tint: parameter(&description.specular_tint, &defaults.specular_tint)?,
+                    sheen: parameter(&description.sheen, &defaults.sheen)?,
+                    clearcoat: parameter(&description.clearcoat, &defaults.clearcoat)?,
+                    transmission: parameter(&description.transmission, &defaults.transmission)?,
+                    index_of_refraction: description.index_of_refraction.unwrap_or(defaults.index_of_refraction),
+                    ..defaults
+                }))
+            },
+            MaterialKind::DiffuseLight { emit } => Material::DiffuseLight { emit: self.texture(emit)? },
+            MaterialKind::Isotropic { albedo } => Material::Isotropic { albedo: self.texture(albedo)? },
+            MaterialKind::Mix { .. } => unreachable!("mix materials are built by `build_material`")
+        })
+    }
+
+    /// Loads the mesh at `path` (relative to the scene file), or returns the copy loaded for an earlier object
+    fn mesh(&mut self, path: &str, smoothing_angle: f64) -> Result<Arc<Mesh>, SceneFileErrorKind> {
+        let key = (self.directory.join(path), smoothing_angle.to_bits());
+        if let Some(mesh) = self.meshes.get(&key) {
+            return Ok(mesh.clone());
+        }
+        let mesh = Mesh::from_file_with_smoothing(&key.0, smoothing_angle).map_err(SceneFileErrorKind::Mesh)?;
+        let mesh = Arc::new(mesh);
+        self.meshes.insert(key, mesh.clone());
+        Ok(mesh)
+    }
+
+    /// Loads the glTF file at `path` (relative to the scene file), or returns the copy loaded earlier
+    fn gltf_scene(&mut self, path: &str) -> Result<Arc<GltfScene>, SceneFileErrorKind> {
+        let path = self.directory.join(path);
+        if let Some(scene) = self.gltf_scenes.get(&path) {
+            return Ok(scene.clone());
+        }
+        let scene = Arc::new(GltfScene::load(&path).map_err(SceneFileErrorKind::Gltf)?);
+        self.gltf_scenes.insert(path, scene.clone());
+        Ok(scene)
+    }
+
+    /// Builds the camera, starting from a glTF camera if there is one and replacing whichever of its settings
+    /// the scene file gives
+    fn build_camera(&mut self, aspect_ratio: f64) -> Result<Camera, SceneFileErrorKind> {
+        let description = &self.description.camera;
+        let imported = match &description.gltf {
+            Some(gltf) => {
+                let scene = self.gltf_scene(&gltf.path)?;
+                Some(*scene.cameras.get(gltf.camera).ok_or(SceneFileErrorKind::UnknownGltfCamera(gltf.camera))?)
+            },
+            None => None
+        };
+        let setting = |value: Option<[f64; 3]>, imported: Option<Vec3>, name| {
+            value.map(vec3).or(imported).ok_or(SceneFileErrorKind::MissingCameraSetting(name))
+        };
+
+        let [time0, time1] = description.shutter;
+        Ok(Camera::custom(
+            setting(description.look_from, imported.map(|camera| camera.look_from), "look_from")?,
+            setting(description.look_at, imported.map(|camera| camera.look_at), "look_at")?,
+            description.up.map(vec3).or(imported.map(|camera| camera.up)).unwrap_or(Vec3(0.0, 1.0, 0.0)),
+            aspect_ratio,
+            description.vfov.or(imported.map(|camera| camera.vfov)).ok_or(SceneFileErrorKind::MissingCameraSetting("vfov"))?,
+            description.aperture,
+            description.focus_distance,
+            time0,
+            time1
+        ))
+    }
+
+    /// Builds an object, and returns it along with which part of it (if any) should be sampled as a light
+    fn build_object(&mut self, object: &ObjectDescription) -> Result<(Box<dyn Hit>, Emission), SceneFileErrorKind> {
+        let material = match &object.material {
+            Some(name) => {
+                Some(self.materials.get(name).cloned().ok_or_else(|| SceneFileErrorKind::UnknownMaterial(name.clone()))?)
+            },
+            None => None
+        };
+        let surface_material = || match (&material, &object.medium) {
+            (Some(material), _) => Ok(material.clone()),
+            // the boundary of a medium is never shaded, so any material will do
+            (None, Some(_)) => Ok(Material::Lambertian { albedo: Arc::new(SolidColor::from(Vec3(0.0, 0.0, 0.0))) }),
+            (None, None) => Err(SceneFileErrorKind::MissingMaterial)
+        };
+
+        let transform = build_transform(TransformData::identity(), &object.transform)?;
+        let animation = if object.keyframes.is_empty() {
+            None
+        }
+        else {
+            let keyframes = object.keyframes.iter()
+                .map(|keyframe| Ok((keyframe.time, build_transform(transform, &keyframe.transform)?)))
+                .collect::<Result<Vec<_>, SceneFileErrorKind>>()?;
+            Some(AnimatedTransform::new(keyframes).ok_or(SceneFileErrorKind::MirroredKeyframes)?)
+        };
+
+        // the parts of the object which are sampled as lights, if it's only partly emissive
+        let mut emissive_parts: Option<Box<dyn Hit>> = None;
+        let mut built: Box<dyn Hit> = match &object.kind {
+            ObjectKind::Sphere { center, radius } => Box::new(Sphere {
+                center: vec3(*center),
+                radius: *radius,
+                material: surface_material()?
+            }),
+            ObjectKind::MovingSphere { start_center, end_center, start_time, end_time, radius } => {
+                Box::new(MovingSphere::new(
+                    vec3(*start_center),
+                    vec3(*end_center),
+                    *start_time,
+                    *end_time,
+                    *radius,
+                    surface_material()?
+                ))
+            },
+            ObjectKind::Rect { plane, a, b, k } => {
+                let material = surface_material()?;
+                match plane {
+                    Plane::Xy => Box::new(XY::new(material, a[0], a[1], b[0], b[1], *k)),
+                    Plane::Xz => Box::new(XZ::new(material, a[0], a[1], b[0], b[1], *k)),
+                    Plane::Yz => Box::new(YZ::new(material, a[0], a[1], b[0], b[1], *k))
+                }
+            },
+            ObjectKind::Block { min, max } => Box::new(Block::new(vec3(*min), vec3(*max), surface_material()?)),
+            ObjectKind::Triangle { vertices } => Box::new(Triangle::new(
+                vec3(vertices[0]),
+                vec3(vertices[1]),
+                vec3(vertices[2]),
+                surface_material()?
+            )),
+            ObjectKind::Mesh { path, smoothing_angle } => {
+                // meshes are always instanced, so every object using the same file shares one copy of it
+                let mesh = self.mesh(path, *smoothing_angle)?;
+                let emissive = emissive_triangles(&mesh, TransformData::identity(), &material);
+                if !emissive.is_empty() {
+                    emissive_parts = Some(Box::new(HittableList::from_vec(emissive)));
+                }
+                let mesh = match &animation {
+                    Some(animation) => Instance::animated(mesh, animation.clone()),
+                    None => Instance::shared(mesh, transform)
+                };
+                match &material {
+                    // an explicit material replaces the ones from the file
+                    Some(material) => Box::new(mesh.with_material(material.clone())),
+                    None => Box::new(mesh)
+                }
+            },
+            ObjectKind::Gltf { path } => {
+                // the file's own instances go in a BVH, which the object's transform (if any) then moves as a whole
+                let scene = self.gltf_scene(path)?;
+                let instances: Vec<Box<dyn Hit>> = scene.instances.iter().map(|(mesh, transform)| {
+                    let instance = Instance::shared(mesh.clone(), *transform);
+                    match &material {
+                        Some(material) => Box::new(instance.with_material(material.clone())) as Box<dyn Hit>,
+                        None => Box::new(instance)
+                    }
+                }).collect();
+                if instances.is_empty() {
+                    return Err(SceneFileErrorKind::Gltf(GltfError::Invalid("no meshes".to_string())));
+                }
+                let emissive: Vec<Box<dyn Hit>> = scene.instances.iter()
+                    .flat_map(|(mesh, transform)| emissive_triangles(mesh, *transform, &material))
+                    .collect();
+                if !emissive.is_empty() {
+                    emissive_parts = Some(Box::new(HittableList::from_vec(emissive)));
+                }
+                let [time0, time1] = self.description.camera.shutter;
+                Box::new(BVH::make_with_strategy(instances, time0, time1, self.description.acceleration.split))
+            }
+        };
+
+        // meshes are already placed by their instance, anything else (and the emissive parts, which
+        // move along with the rest of the object) is placed here
+        let instanced = matches!(object.kind, ObjectKind::Mesh { .. });
+        if let Some(animation) = animation {
+            emissive_parts = emissive_parts.map(|parts| Box::new(Instance::animated(Arc::from(parts), animation.clone())) as Box<dyn Hit>);
+            if !instanced {
+                built = Box::new(Instance::animated(Arc::from(built), animation));
+            }
+        }
+        else if !object.transform.is_empty() {
+            emissive_parts = emissive_parts.map(|parts| Box::new(Instance::new(parts, transform)) as Box<dyn Hit>);
+            if !instanced {
+                built = Box::new(Instance::new(built, transform));
+            }
+        }
+
+        if let Some(medium) = &object.medium {
+            built = Box::new(ConstantMedium::new(built, medium.density, self.texture(&medium.albedo)?));
+        }
+
+        let emission = match emissive_parts {
+            _ if object.medium.is_some() => Emission::None,
+            Some(parts) => Emission::Parts(parts),
+            None if !matches!(object.kind, ObjectKind::Mesh { .. } | ObjectKind::Gltf { .. })
+                && matches!(material.as_ref().map(Material::unshared), Some(Material::DiffuseLight { .. })) => Emission::Whole,
+            None => Emission::None
+        };
+        Ok((built, emission))
+    }
+}
+
+/// Which part of a built object, if any, is sampled as a light
+enum Emission {
+    None,
+    /// The whole object is a light
+    Whole,
+    /// Only these parts of the object emit light, e.g the emissive triangles of a mesh or glTF scene
+    Parts(Box<dyn Hit>)
+}
+
+/// The triangles of `mesh` with an emissive material, placed by `transform`. If `material` is given it replaces
+/// the mesh's own materials, so either every triangle emits or none do
+fn emissive_triangles(mesh: &Mesh, transform: TransformData, material: &Option<Material>) -> Vec<Box<dyn Hit>> {
+    mesh.triangles.iter()
+        .filter_map(|triangle| {
+            let material = material.as_ref().unwrap_or(&mesh.materials[triangle.material_index]);
+            if !matches!(material.unshared(), Material::DiffuseLight { .. }) {
+                return None;
+            }
+            let [v0, v1, v2] = triangle.vertex_indices.map(|i| mesh.vertices[i].transform(transform));
+            Some(Box::new(Triangle::new(v0, v1, v2, material.clone())) as Box<dyn Hit>)
+        })
+        .collect()
+}
\ No newline at end of file
@@ -44,9 +44,45 @@ impl AABB {
         }
     }
 
+    pub fn centroid(&self) -> Vec3 {
+        0.5 * (self.minimum + self.maximum)
+    }
+
+    /// The 8 corners of the box
+    pub fn corners(&self) -> [Vec3; 8] {
+        let (min, max) = (self.minimum, self.maximum);
+        [
+            Vec3(min.0, min.1, min.2), Vec3(max.0, min.1, min.2), Vec3(min.0, max.1, min.2), Vec3(max.0, max.1, min.2),
+            Vec3(min.0, min.1, max.2), Vec3(max.0, min.1, max.2), Vec3(min.0, max.1, max.2), Vec3(max.0, max.1, max.2)
+        ]
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let extent = self.maximum - self.minimum;
+        2.0 * (extent.0 * extent.1 + extent.1 * extent.2 + extent.2 * extent.0)
+    }
+
     pub fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> bool {
         AABB::check_slab(self.minimum.0, self.maximum.0, r.origin.0, r.direction.0, t_min, t_max)
         && AABB::check_slab(self.minimum.1, self.maximum.1, r.origin.1, r.direction.1, t_min, t_max)
         && AABB::check_slab(self.minimum.2, self.maximum.2, r.origin.2, r.direction.2, t_min, t_max)
     }
-}
+
+    /// Same as `hit`, but with the reciprocal of the ray direction computed up front,
+    /// as it's the same for every box a ray is tested against while traversing a BVH.
+    /// Unlike `hit`, rays which only touch the box count, so flat boxes (e.g around axis-aligned triangles) can be hit
+    pub fn hit_inverse(&self, origin: Vec3, inverse_direction: Vec3, t_min: f64, t_max: f64) -> bool {
+        let mut t_min = t_min;
+        let mut t_max = t_max;
+        for axis in 0..3 {
+            let x = (self.minimum[axis] - origin[axis]) * inverse_direction[axis];
+            let y = (self.maximum[axis] - origin[axis]) * inverse_direction[axis];
+            t_min = f64::max(t_min, f64::min(x, y));
+            t_max = f64::min(t_max, f64::max(x, y));
+            if t_max < t_min {
+                return false;
+            }
+        }
+        true
+    }
+}
\ No newline at end of file
@@ -1,129 +1,365 @@
-use std::{cmp::Ordering, mem::swap};
+use std::{
+    collections::BTreeMap,
+    fmt::Display
+};
 
+use clap::clap_derive::ArgEnum;
 use rand::Rng;
+use serde::Deserialize;
 
-use crate::hittables::hittable::{Hit, HitRecord};
+use crate::hittables::hittable::{
+    Hit,
+    HitRecord
+};
 
-use super::{aabb::AABB, ray::Ray};
+use super::{
+    aabb::AABB,
+    ray::Ray,
+    vec3::Vec3
+};
 
-/// A node in the bounding volume hierarchy
+/// How objects are divided between the two children of each node when building a BVH
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ArgEnum, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SplitStrategy {
+    /// Split at the median along a random axis
+    Random,
+    /// Split at the median along the axis the objects' centers are most spread out on
+    Median,
+    /// Split to minimize the Surface Area Heuristic, which estimates the cost of tracing rays
+    /// through the node from the surface areas of the children
+    #[default]
+    Sah
+}
+
+/// Number of buckets objects are sorted into along each axis when evaluating SAH splits
+const SAH_BINS: usize = 12;
+/// Cost of traversing a node, relative to intersecting an object
+const TRAVERSAL_COST: f64 = 0.125;
+/// Largest number of objects the SAH will put in a single leaf
+const MAX_LEAF_SIZE: usize = 4;
+/// Size of the traversal stack. Below `BALANCED_DEPTH` nodes are always split at the median,
+/// which keeps the depth of the tree (and so the number of nodes waiting on the stack) well under this
+const STACK_SIZE: usize = 64;
+const BALANCED_DEPTH: usize = 32;
+
+/// Statistics on the shape of a BVH, for comparing split strategies
+/// # Fields
+/// `depth` - number of nodes on the longest path from the root to a leaf (including both)
+///
+/// `leaf_sizes` - number of leaves holding each number of objects
+#[derive(Debug, Clone, Default)]
+pub struct BVHStatistics {
+    pub nodes: usize,
+    pub leaves: usize,
+    pub depth: usize,
+    pub leaf_sizes: BTreeMap<usize, usize>
+}
+
+impl Display for BVHStatistics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "nodes: {}, leaves: {}, depth: {}", self.nodes, self.leaves, self.depth)?;
+        write!(f, "leaf sizes:")?;
+        for (size, count) in &self.leaf_sizes {
+            write!(f, " {}: {}", size, count)?;
+        }
+        Ok(())
+    }
+}
 
-pub enum BVHNode {
+#[derive(Clone, Copy)]
+enum NodeKind {
+    /// Refers to the primitives `first..first + count`
     Leaf {
-        val: Box<dyn Hit>,
-        bounding_box: AABB
+        first: usize,
+        count: usize
     },
-    Branch {
-        left: Box<dyn Hit>,
-        right: Box<dyn Hit>,
-        bounding_box: AABB
+    /// The first child directly follows its parent, so only the index of the second is stored.
+    /// `axis` is the axis the children were split along, which decides which to visit first
+    Interior {
+        second_child: usize,
+        axis: usize
     }
 }
 
-impl BVHNode {
-    pub fn make(mut objects: Vec<Box<dyn Hit>>, t0: f64, t1: f64) -> BVHNode {
-        let comparator = match rand::thread_rng().gen_range(0..3) {
-            0 => |a: AABB, b: AABB| if a.minimum.0 < b.minimum.0 { Ordering::Less } else { Ordering::Greater },
-            1 => |a: AABB, b: AABB| if a.minimum.1 < b.minimum.1 { Ordering::Less } else { Ordering::Greater },
-            _ => |a: AABB, b: AABB| if a.minimum.2 < b.minimum.2 { Ordering::Less } else { Ordering::Greater },
-        };
-        if objects.len() == 1 {
-            let leaf = objects.remove(0);
-            let bounding_box = leaf.bounding_box(t0, t1).expect("objects in BVH must be boundable");
-            BVHNode::Leaf {
-                val: leaf,
-                bounding_box: bounding_box
-            }
-        }
-        else if objects.len() == 2 {
-            let mut a_aabb = objects[0].bounding_box(t0, t1).expect("objects in BVH must be boundable");
-            let mut b_aabb = objects[1].bounding_box(t0, t1).expect("objects in BVH must be boundable");
-            if comparator(a_aabb, b_aabb) == Ordering::Greater {
-                objects.swap(0, 1);
-                swap(&mut a_aabb, &mut b_aabb)
-            }
-            BVHNode::Branch { 
-                left: Box::new(BVHNode::Leaf {
-                    val: objects.remove(0),
-                    bounding_box: a_aabb
-                }),
-                right: Box::new(BVHNode::Leaf {
-                    val: objects.remove(0), 
-                    bounding_box: b_aabb
-                }),
-                bounding_box: AABB::surrounding_box(a_aabb, b_aabb)
-            }
+#[derive(Clone, Copy)]
+struct LinearNode {
+    bounding_box: AABB,
+    kind: NodeKind
+}
+
+/// A bounding volume hierarchy stored as a flat array of nodes in depth first order.
+/// It only deals with bounding boxes and indices - the primitives themselves are kept by the owner,
+/// in the order returned by `build`, and intersected through the closure passed to `hit`
+pub struct LinearBVH {
+    nodes: Vec<LinearNode>
+}
+
+impl LinearBVH {
+    /// Builds a hierarchy over primitives with the given bounding boxes. Also returns the order the
+    /// primitives need to be stored in, as leaves refer to contiguous ranges of them
+    pub fn build(bounds: &[AABB], strategy: SplitStrategy) -> (LinearBVH, Vec<usize>) {
+        assert!(!bounds.is_empty(), "cannot build a BVH without any objects");
+        let mut primitives: Vec<(usize, AABB)> = bounds.iter().copied().enumerate().collect();
+        let mut bvh = LinearBVH { nodes: Vec::with_capacity(2 * bounds.len()) };
+        bvh.build_recursive(&mut primitives, 0, strategy, 1);
+        let order = primitives.into_iter().map(|(index, _)| index).collect();
+        (bvh, order)
+    }
+
+    /// Adds the node for `primitives` (and the nodes under it), which start at `first` in the final order
+    fn build_recursive(&mut self, primitives: &mut [(usize, AABB)], first: usize, strategy: SplitStrategy, depth: usize) {
+        let bounding_box = LinearBVH::surrounding(primitives);
+        let count = primitives.len();
+        let strategy = if depth >= BALANCED_DEPTH { SplitStrategy::Median } else { strategy };
+        let split = if count == 1 {
+            None
         }
         else {
-            objects.sort_by(|a, b| {
-                comparator(a.bounding_box(t0, t1).expect("objects in BVH must be boundable"),
-                           b.bounding_box(t0, t1).expect("objects in BVH must be boundable"))
-            });
-            let right = objects.split_off(objects.len() / 2);
-            let left = BVHNode::make(objects, t0, t1);
-            let right = BVHNode::make(right, t0, t1);
-            let bounding_box = AABB::surrounding_box(left.get_bounding_box(), right.get_bounding_box());
-            BVHNode::Branch { 
-                left: Box::new(left),
-                right: Box::new(right),
-                bounding_box
+            match strategy {
+                SplitStrategy::Random => {
+                    let axis = rand::thread_rng().gen_range(0..3);
+                    primitives.sort_by(|(_, a), (_, b)| a.minimum[axis].total_cmp(&b.minimum[axis]));
+                    Some((count / 2, axis))
+                },
+                SplitStrategy::Median => {
+                    let axis = LinearBVH::split_median(primitives);
+                    Some((count / 2, axis))
+                },
+                SplitStrategy::Sah => LinearBVH::split_sah(primitives)
             }
-        }
+        };
 
-    }
-    fn get_bounding_box(&self) -> AABB {
-        match self {
-            Self::Leaf { bounding_box, .. } => {
-                *bounding_box
+        let index = self.nodes.len();
+        match split {
+            Some((split, axis)) => {
+                self.nodes.push(LinearNode { bounding_box, kind: NodeKind::Interior { second_child: 0, axis } });
+                let (left, right) = primitives.split_at_mut(split);
+                self.build_recursive(left, first, strategy, depth + 1);
+                let second = self.nodes.len();
+                self.build_recursive(right, first + split, strategy, depth + 1);
+                self.nodes[index].kind = NodeKind::Interior { second_child: second, axis };
             },
-            Self::Branch { bounding_box, .. } => {
-                *bounding_box
+            None => {
+                self.nodes.push(LinearNode { bounding_box, kind: NodeKind::Leaf { first, count } });
             }
         }
     }
-}
 
-impl Hit for BVHNode {
-    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        match self {
-            Self::Leaf { val, bounding_box } => {
-                if !bounding_box.hit(r, t_min, t_max) {
-                    None
+    fn surrounding(primitives: &[(usize, AABB)]) -> AABB {
+        primitives.iter()
+            .map(|(_, bounding_box)| *bounding_box)
+            .reduce(AABB::surrounding_box)
+            .expect("BVH nodes are never empty")
+    }
+
+    /// Box surrounding the centers of the bounding boxes of `primitives`
+    fn centroid_bounds(primitives: &[(usize, AABB)]) -> AABB {
+        primitives.iter()
+            .map(|(_, bounding_box)| AABB::new(bounding_box.centroid(), bounding_box.centroid()))
+            .reduce(AABB::surrounding_box)
+            .expect("BVH nodes are never empty")
+    }
+
+    fn largest_axis(bounds: AABB) -> usize {
+        let extent = bounds.maximum - bounds.minimum;
+        if extent.0 > extent.1 && extent.0 > extent.2 { 0 } else if extent.1 > extent.2 { 1 } else { 2 }
+    }
+
+    /// Sorts `primitives` by their centers along the axis they're most spread out on, and returns that axis
+    fn split_median(primitives: &mut [(usize, AABB)]) -> usize {
+        let axis = LinearBVH::largest_axis(LinearBVH::centroid_bounds(primitives));
+        primitives.sort_by(|(_, a), (_, b)| a.centroid()[axis].total_cmp(&b.centroid()[axis]));
+        axis
+    }
+
+    /// Sorts primitives into buckets along each axis, and picks the boundary between buckets with the lowest
+    /// SAH cost. Partitions `primitives` at that boundary and returns the index of the first primitive on the right
+    /// along with the axis, or None if the primitives are cheaper to intersect as a single leaf
+    fn split_sah(primitives: &mut [(usize, AABB)]) -> Option<(usize, usize)> {
+        let count = primitives.len();
+        let bounds = LinearBVH::surrounding(primitives);
+        let centroid_bounds = LinearBVH::centroid_bounds(primitives);
+        let bucket = |bounding_box: &AABB, axis: usize| {
+            let extent = centroid_bounds.maximum[axis] - centroid_bounds.minimum[axis];
+            let offset = (bounding_box.centroid()[axis] - centroid_bounds.minimum[axis]) / extent;
+            ((offset * SAH_BINS as f64) as usize).min(SAH_BINS - 1)
+        };
+
+        // (cost, axis, number of buckets on the left)
+        let mut best: Option<(f64, usize, usize)> = None;
+        for axis in 0..3 {
+            if centroid_bounds.maximum[axis] <= centroid_bounds.minimum[axis] {
+                continue;
+            }
+
+            let mut bins: [(usize, Option<AABB>); SAH_BINS] = [(0, None); SAH_BINS];
+            for (_, bounding_box) in primitives.iter() {
+                let (bin_count, bin_bounds) = &mut bins[bucket(bounding_box, axis)];
+                *bin_count += 1;
+                *bin_bounds = Some(bin_bounds.map_or(*bounding_box, |b| AABB::surrounding_box(b, *bounding_box)));
+            }
+
+            // sweep from the right to find the area and count right of each boundary, then from the left
+            let mut right_area = [0.0; SAH_BINS];
+            let mut right_count = [0; SAH_BINS];
+            let mut accumulated: Option<AABB> = None;
+            let mut accumulated_count = 0;
+            for i in (1..SAH_BINS).rev() {
+                accumulated = LinearBVH::merge(accumulated, bins[i].1);
+                accumulated_count += bins[i].0;
+                right_area[i] = accumulated.map_or(0.0, |b| b.surface_area());
+                right_count[i] = accumulated_count;
+            }
+
+            let mut accumulated: Option<AABB> = None;
+            let mut accumulated_count = 0;
+            for i in 1..SAH_BINS {
+                accumulated = LinearBVH::merge(accumulated, bins[i - 1].1);
+                accumulated_count += bins[i - 1].0;
+                if accumulated_count == 0 || right_count[i] == 0 {
+                    continue;
                 }
-                else {
-                    val.hit(r, t_min, t_max)
+                let left_area = accumulated.map_or(0.0, |b| b.surface_area());
+                let cost = TRAVERSAL_COST
+                    + (left_area * accumulated_count as f64 + right_area[i] * right_count[i] as f64) / bounds.surface_area();
+                if best.is_none_or(|(best_cost, _, _)| cost < best_cost) {
+                    best = Some((cost, axis, i));
                 }
-            },
-            Self::Branch { left, right, bounding_box } => {
-                if !bounding_box.hit(r, t_min, t_max) {
-                    None
+            }
+        }
+
+        match best {
+            Some((cost, axis, split)) => {
+                if count <= MAX_LEAF_SIZE && cost >= count as f64 {
+                    return None;
                 }
-                else {
-                    let left = left.hit(r, t_min, t_max);
-                    let t_max = if let Some(ref left_hitrecord) = left { left_hitrecord.t } else { t_max };
-                    let right = right.hit(r, t_min, t_max);
-                    if right.is_some() {
-                        right
-                    }
-                    else if left.is_some() {
-                        left
-                    }
-                    else {
-                        None
+                primitives.sort_by_key(|(_, bounding_box)| bucket(bounding_box, axis));
+                let split = primitives.iter().position(|(_, bounding_box)| bucket(bounding_box, axis) >= split).unwrap_or(count);
+                Some((split, axis))
+            },
+            // every primitive has the same center, so there's nothing to tell them apart by
+            None if count <= MAX_LEAF_SIZE => None,
+            None => Some((count / 2, LinearBVH::largest_axis(bounds)))
+        }
+    }
+
+    fn merge(a: Option<AABB>, b: Option<AABB>) -> Option<AABB> {
+        match (a, b) {
+            (Some(a), Some(b)) => Some(AABB::surrounding_box(a, b)),
+            (a, None) => a,
+            (None, b) => b
+        }
+    }
+
+    pub fn bounding_box(&self) -> AABB {
+        self.nodes[0].bounding_box
+    }
+
+    /// Finds the closest hit along `r`, calling `hit_primitive` with the index (in the order returned by `build`)
+    /// of every primitive in a leaf the ray passes through. Children are visited nearest first, judged by the
+    /// sign of the ray direction along the axis they were split on, so that far nodes can be skipped once
+    /// something closer has been hit
+    pub fn hit<'a, F>(&self, r: Ray, t_min: f64, t_max: f64, mut hit_primitive: F) -> Option<HitRecord<'a>>
+    where
+        F: FnMut(usize, Ray, f64, f64) -> Option<HitRecord<'a>>
+    {
+        let inverse_direction = Vec3(1.0 / r.direction.0, 1.0 / r.direction.1, 1.0 / r.direction.2);
+        let negative = [inverse_direction.0 < 0.0, inverse_direction.1 < 0.0, inverse_direction.2 < 0.0];
+
+        let mut stack = [0; STACK_SIZE];
+        let mut stack_size = 0;
+        let mut current = 0;
+        let mut closest = t_max;
+        let mut result = None;
+        loop {
+            let node = &self.nodes[current];
+            if node.bounding_box.hit_inverse(r.origin, inverse_direction, t_min, closest) {
+                match node.kind {
+                    NodeKind::Leaf { first, count } => {
+                        for index in first..first + count {
+                            if let Some(record) = hit_primitive(index, r, t_min, closest) {
+                                closest = record.t;
+                                result = Some(record);
+                            }
+                        }
+                    },
+                    NodeKind::Interior { second_child, axis } => {
+                        let (near, far) = if negative[axis] { (second_child, current + 1) } else { (current + 1, second_child) };
+                        stack[stack_size] = far;
+                        stack_size += 1;
+                        current = near;
+                        continue;
                     }
                 }
             }
+            if stack_size == 0 {
+                break;
+            }
+            stack_size -= 1;
+            current = stack[stack_size];
         }
+        result
     }
 
-    fn bounding_box(&self, _t0: f64, _t1: f64) -> Option<AABB> {
-        match self {
-            Self::Leaf { bounding_box, .. } => {
-                Some(*bounding_box)
-            },
-            Self::Branch { bounding_box, .. } => {
-                Some(*bounding_box)
+    /// Counts the nodes and leaves of this BVH
+    pub fn statistics(&self) -> BVHStatistics {
+        let mut statistics = BVHStatistics::default();
+        let mut stack = vec![(0, 1)];
+        while let Some((index, depth)) = stack.pop() {
+            statistics.nodes += 1;
+            statistics.depth = statistics.depth.max(depth);
+            match self.nodes[index].kind {
+                NodeKind::Leaf { count, .. } => {
+                    statistics.leaves += 1;
+                    *statistics.leaf_sizes.entry(count).or_insert(0) += 1;
+                },
+                NodeKind::Interior { second_child, .. } => {
+                    stack.push((index + 1, depth + 1));
+                    stack.push((second_child, depth + 1));
+                }
             }
         }
+        statistics
+    }
+}
+
+/// A BVH over arbitrary hittables
+pub struct BVH {
+    tree: LinearBVH,
+    objects: Vec<Box<dyn Hit>>
+}
+
+impl BVH {
+    /// Builds a BVH using the default split strategy (SAH)
+    pub fn make(objects: Vec<Box<dyn Hit>>, t0: f64, t1: f64) -> BVH {
+        BVH::make_with_strategy(objects, t0, t1, SplitStrategy::default())
+    }
+
+    pub fn make_with_strategy(objects: Vec<Box<dyn Hit>>, t0: f64, t1: f64, strategy: SplitStrategy) -> BVH {
+        let bounds: Vec<AABB> = objects.iter()
+            .map(|object| object.bounding_box(t0, t1).expect("objects in BVH must be boundable"))
+            .collect();
+        let (tree, order) = LinearBVH::build(&bounds, strategy);
+
+        let mut objects: Vec<Option<Box<dyn Hit>>> = objects.into_iter().map(Some).collect();
+        let objects = order.into_iter()
+            .map(|index| objects[index].take().expect("every object appears once in the BVH"))
+            .collect();
+        BVH { tree, objects }
+    }
+
+    pub fn statistics(&self) -> BVHStatistics {
+        self.tree.statistics()
+    }
+}
+
+impl Hit for BVH {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.tree.hit(r, t_min, t_max, |index, r, t_min, t_max| self.objects[index].hit(r, t_min, t_max))
+    }
+
+    fn bounding_box(&self, _t0: f64, _t1: f64) -> Option<AABB> {
+        Some(self.tree.bounding_box())
     }
 }
\ No newline at end of file
@@ -6,4 +6,9 @@ pub type Color = Vec3;
 
 pub fn random_color() -> Color {
     Vec3(random(), random(), random())
+}
+
+/// Relative luminance of a linear RGB color (Rec. 709 primaries)
+pub fn luminance(color: Color) -> f64 {
+    0.2126 * color.x() + 0.7152 * color.y() + 0.0722 * color.z()
 }
\ No newline at end of file
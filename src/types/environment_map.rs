@@ -0,0 +1,200 @@
+use std::{f64::consts::PI, fmt::Debug, fs::File, io::BufReader, path::Path};
+
+use image::{
+    codecs::hdr::HdrDecoder,
+    ImageError
+};
+use rand::random;
+
+use super::{
+    vec3::Vec3,
+    color::{
+        Color,
+        luminance
+    }
+};
+
+/// An equirectangular (latitude-longitude) image of the light arriving from every direction,
+/// with +y up and the center of the image looking down -z.
+/// Directions are importance sampled by the luminance of the pixel they land on
+/// # Fields
+/// `rotation` - radians the map is turned about the y axis, counter-clockwise seen from above (so turning
+/// by 90 degrees moves what was towards +x to -z)
+///
+/// `intensity` - scales the radiance of every pixel
+pub struct EnvironmentMap {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+    rotation: f64,
+    intensity: f64,
+    distribution: Distribution2D
+}
+
+impl Debug for EnvironmentMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EnvironmentMap")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("rotation", &self.rotation)
+            .field("intensity", &self.intensity)
+            .finish()
+    }
+}
+
+impl EnvironmentMap {
+    /// Loads a high dynamic range image (Radiance .hdr or OpenEXR), or any other format the `image` crate
+    /// can read. `rotation` is in degrees
+    pub fn open(path: &Path, rotation: f64, intensity: f64) -> Result<EnvironmentMap, ImageError> {
+        let is_hdr = path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("hdr"));
+        let (width, height, pixels) = if is_hdr {
+            // decoding .hdr files into a `DynamicImage` clamps them to 8 bits, so they're read directly
+            let decoder = HdrDecoder::new(BufReader::new(File::open(path).map_err(ImageError::IoError)?))?;
+            let metadata = decoder.metadata();
+            let pixels = decoder.read_image_hdr()?.into_iter()
+                .map(|pixel| Vec3(pixel.0[0] as f64, pixel.0[1] as f64, pixel.0[2] as f64))
+                .collect();
+            (metadata.width, metadata.height, pixels)
+        }
+        else {
+            let image = image::open(path)?.into_rgb32f();
+            let pixels = image.pixels()
+                .map(|pixel| Vec3(pixel.0[0] as f64, pixel.0[1] as f64, pixel.0[2] as f64))
+                .collect();
+            (image.width(), image.height(), pixels)
+        };
+        Ok(EnvironmentMap::new(width as usize, height as usize, pixels, rotation, intensity))
+    }
+
+    /// Environment map from rows of pixels, starting at the top (+y)
+    pub fn new(width: usize, height: usize, pixels: Vec<Color>, rotation: f64, intensity: f64) -> EnvironmentMap {
+        assert!(width > 0 && height > 0 && pixels.len() == width * height, "environment map must have width * height pixels");
+        // rows near the poles are squashed into less solid angle, so they're sampled less
+        let weights: Vec<Vec<f64>> = (0..height).map(|y| {
+            let sin_theta = (PI * (y as f64 + 0.5) / height as f64).sin();
+            pixels[y * width..(y + 1) * width].iter()
+                .map(|&pixel| luminance(pixel).max(0.0) * sin_theta)
+                .collect()
+        }).collect();
+
+        EnvironmentMap {
+            width,
+            height,
+            pixels,
+            rotation: rotation.to_radians(),
+            intensity,
+            distribution: Distribution2D::new(weights)
+        }
+    }
+
+    /// Radiance arriving from `direction` (which doesn't need to be normalized)
+    pub fn radiance(&self, direction: Vec3) -> Color {
+        let (u, v) = self.uv(direction);
+        let x = ((u * self.width as f64) as usize).min(self.width - 1);
+        let y = ((v * self.height as f64) as usize).min(self.height - 1);
+        self.intensity * self.pixels[y * self.width + x]
+    }
+
+    /// Picks a random (unit) direction, more likely towards brighter parts of the map
+    pub fn random(&self) -> Vec3 {
+        let (u, v) = self.distribution.sample(random(), random());
+        let theta = v * PI;
+        let phi = (u - 0.5) * 2.0 * PI - self.rotation;
+        Vec3(theta.sin() * phi.sin(), theta.cos(), -theta.sin() * phi.cos())
+    }
+
+    /// Probability density (with respect to solid angle) of `random` returning `direction`
+    pub fn pdf_value(&self, direction: Vec3) -> f64 {
+        let (u, v) = self.uv(direction);
+        let sin_theta = (v * PI).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        // the map covers 2 pi by pi radians, with the area of each pixel shrunk by sin(theta)
+        self.distribution.pdf(u, v) / (2.0 * PI * PI * sin_theta)
+    }
+
+    /// Position of `direction` in the image, from (0, 0) at the top left to (1, 1) at the bottom right
+    fn uv(&self, direction: Vec3) -> (f64, f64) {
+        let direction = Vec3::normalized(direction);
+        let phi = f64::atan2(direction.x(), -direction.z()) + self.rotation;
+        let u = (phi / (2.0 * PI) + 0.5).rem_euclid(1.0);
+        let v = direction.y().clamp(-1.0, 1.0).acos() / PI;
+        (u, v)
+    }
+}
+
+/// Piecewise constant distribution over [0, 1), for sampling in proportion to `weights`
+struct Distribution1D {
+    weights: Vec<f64>,
+    /// `cdf[i]` is the chance of sampling before piece `i`, so it starts at 0 and ends at 1
+    cdf: Vec<f64>,
+    /// average of the weights, which normalizes them into a density
+    integral: f64
+}
+
+impl Distribution1D {
+    fn new(weights: Vec<f64>) -> Distribution1D {
+        let count = weights.len() as f64;
+        let mut cdf = Vec::with_capacity(weights.len() + 1);
+        cdf.push(0.0);
+        for weight in &weights {
+            cdf.push(cdf[cdf.len() - 1] + weight / count);
+        }
+        let integral = cdf[weights.len()];
+        if integral > 0.0 {
+            for value in cdf.iter_mut() {
+                *value /= integral;
+            }
+        }
+        else {
+            // nothing to prefer, so sample uniformly
+            for (i, value) in cdf.iter_mut().enumerate() {
+                *value = i as f64 / count;
+            }
+        }
+        Distribution1D { weights, cdf, integral }
+    }
+
+    /// Maps `xi` in [0, 1) to a sample, and returns it along with the index of the piece it's in
+    fn sample(&self, xi: f64) -> (f64, usize) {
+        let index = (self.cdf.partition_point(|&value| value <= xi) - 1).min(self.weights.len() - 1);
+        let width = self.cdf[index + 1] - self.cdf[index];
+        let offset = if width > 0.0 { (xi - self.cdf[index]) / width } else { 0.0 };
+        ((index as f64 + offset) / self.weights.len() as f64, index)
+    }
+
+    /// Density of `sample` returning a point in piece `index`
+    fn pdf(&self, index: usize) -> f64 {
+        if self.integral > 0.0 { self.weights[index] / self.integral } else { 1.0 }
+    }
+}
+
+/// Piecewise constant distribution over [0, 1) x [0, 1), sampled by picking a row from the marginal
+/// distribution over rows, then a column from that row's own distribution
+struct Distribution2D {
+    rows: Vec<Distribution1D>,
+    marginal: Distribution1D
+}
+
+impl Distribution2D {
+    /// `weights` is a list of rows
+    fn new(weights: Vec<Vec<f64>>) -> Distribution2D {
+        let rows: Vec<Distribution1D> = weights.into_iter().map(Distribution1D::new).collect();
+        let marginal = Distribution1D::new(rows.iter().map(|row| row.integral).collect());
+        Distribution2D { rows, marginal }
+    }
+
+    fn sample(&self, xi_u: f64, xi_v: f64) -> (f64, f64) {
+        let (v, row) = self.marginal.sample(xi_v);
+        let (u, _) = self.rows[row].sample(xi_u);
+        (u, v)
+    }
+
+    fn pdf(&self, u: f64, v: f64) -> f64 {
+        let row = ((v * self.rows.len() as f64) as usize).min(self.rows.len() - 1);
+        let columns = self.rows[row].weights.len();
+        let column = ((u * columns as f64) as usize).min(columns - 1);
+        self.marginal.pdf(row) * self.rows[row].pdf(column)
+    }
+}
\ No newline at end of file
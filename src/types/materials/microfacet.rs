@@ -0,0 +1,211 @@
+use std::f64::consts::PI;
+
+use rand::random;
+
+use crate::types::{
+    vec3::Vec3,
+    color::Color
+};
+
+// Microfacet models, following "Microfacet Models for Refraction through Rough Surfaces" (Walter et al. 2007)
+// and "Sampling the GGX Distribution of Visible Normals" (Heitz 2018).
+// All directions are in a local shading frame, where the surface normal is +Z
+
+/// Complex index of refraction of a conductor, per RGB channel
+/// `eta` - real part (the usual index of refraction)
+///
+/// `k` - imaginary part (absorption coefficient)
+#[derive(Clone, Copy, Debug)]
+pub struct ComplexIor {
+    pub eta: Color,
+    pub k: Color
+}
+
+impl ComplexIor {
+    pub const GOLD: ComplexIor = ComplexIor { eta: Vec3(0.143, 0.374, 1.442), k: Vec3(3.983, 2.385, 1.603) };
+    pub const COPPER: ComplexIor = ComplexIor { eta: Vec3(0.200, 0.924, 1.102), k: Vec3(3.912, 2.452, 2.142) };
+
+    /// Fresnel reflectance of a conductor, for light arriving at an angle with cosine `cos_theta`
+    /// to the (micro)surface normal
+    pub fn fresnel(&self, cos_theta: f64) -> Color {
+        Vec3(
+            ComplexIor::fresnel_channel(cos_theta, self.eta.0, self.k.0),
+            ComplexIor::fresnel_channel(cos_theta, self.eta.1, self.k.1),
+            ComplexIor::fresnel_channel(cos_theta, self.eta.2, self.k.2)
+        )
+    }
+
+    fn fresnel_channel(cos_theta: f64, eta: f64, k: f64) -> f64 {
+        let cos2 = cos_theta.clamp(0.0, 1.0).powi(2);
+        let sin2 = 1.0 - cos2;
+        let eta2 = eta * eta;
+        let k2 = k * k;
+
+        let t0 = eta2 - k2 - sin2;
+        let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+        let t1 = a2_plus_b2 + cos2;
+        let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+        let t2 = 2.0 * cos_theta * a;
+        let rs = (t1 - t2) / (t1 + t2);
+
+        let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+
+        0.5 * (rp + rs)
+    }
+}
+
+/// Isotropic GGX / Trowbridge-Reitz distribution of microfacet normals
+#[derive(Clone, Copy, Debug)]
+pub struct TrowbridgeReitz {
+    alpha: f64
+}
+
+impl TrowbridgeReitz {
+    /// Perfectly smooth surfaces would make the distribution a delta function, so `alpha`
+    /// is kept slightly above 0
+    const MIN_ALPHA: f64 = 1e-3;
+
+    /// Uses the common remapping alpha = roughness², which is perceptually closer to linear
+    pub fn from_roughness(roughness: f64) -> TrowbridgeReitz {
+        let roughness = roughness.clamp(0.0, 1.0);
+        TrowbridgeReitz { alpha: f64::max(roughness * roughness, TrowbridgeReitz::MIN_ALPHA) }
+    }
+
+    /// Density of microfacets with normal `wh`
+    pub fn d(&self, wh: Vec3) -> f64 {
+        let cos2 = wh.z() * wh.z();
+        if cos2 <= 0.0 {
+            return 0.0;
+        }
+        let alpha2 = self.alpha * self.alpha;
+        let denominator = cos2 * (alpha2 - 1.0) + 1.0;
+        alpha2 / (PI * denominator * denominator)
+    }
+
+    /// Smith's auxiliary function, measuring invisible microfacet area per visible area in direction `w`
+    fn lambda(&self, w: Vec3) -> f64 {
+        let cos2 = w.z() * w.z();
+        if cos2 <= 0.0 {
+            return 0.0;
+        }
+        let tan2 = (1.0 - cos2) / cos2;
+        0.5 * (-1.0 + (1.0 + self.alpha * self.alpha * tan2).sqrt())
+    }
+
+    /// Fraction of microfacets visible from direction `w`
+    pub fn g1(&self, w: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// Fraction of microfacets visible from both `wo` and `wi` (height-correlated Smith shadowing-masking)
+    pub fn g(&self, wo: Vec3, wi: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Samples a microfacet normal from the distribution of normals visible from `wo`,
+    /// which must be in the upper hemisphere
+    pub fn sample_wh(&self, wo: Vec3) -> Vec3 {
+        let u1 = random::<f64>();
+        let u2 = random::<f64>();
+
+        // stretch view direction into configuration where alpha = 1
+        let vh = Vec3::normalized(Vec3(self.alpha * wo.x(), self.alpha * wo.y(), wo.z()));
+
+        let length_squared = vh.x() * vh.x() + vh.y() * vh.y();
+        let t1 = if length_squared > 0.0 {
+            Vec3(-vh.y(), vh.x(), 0.0) / length_squared.sqrt()
+        }
+        else {
+            Vec3(1.0, 0.0, 0.0)
+        };
+        let t2 = Vec3::cross(vh, t1);
+
+        // uniformly sample the projected area of the hemisphere seen from vh
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z());
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+
+        // unstretch
+        Vec3::normalized(Vec3(self.alpha * nh.x(), self.alpha * nh.y(), nh.z().max(0.0)))
+    }
+
+    /// Density of `sample_wh` returning `wh`
+    pub fn pdf_wh(&self, wo: Vec3, wh: Vec3) -> f64 {
+        if wo.z() <= 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) * Vec3::dot(wo, wh).max(0.0) * self.d(wh) / wo.z()
+    }
+}
+
+/// Evaluates a rough conductor: returns the BRDF multiplied by the cosine term for light arriving from `wi`
+/// and leaving towards `wo`, along with the density of sampling `wi` by reflecting `wo` about a visible normal
+pub fn eval_conductor(distribution: TrowbridgeReitz, ior: ComplexIor, wo: Vec3, wi: Vec3) -> (Color, f64) {
+    if wo.z() <= 0.0 || wi.z() <= 0.0 {
+        return (Vec3(0.0, 0.0, 0.0), 0.0);
+    }
+    let wh = Vec3::normalized(wo + wi);
+    let fresnel = ior.fresnel(Vec3::dot(wo, wh));
+    let value = distribution.d(wh) * distribution.g(wo, wi) * fresnel / (4.0 * wo.z());
+    let pdf = distribution.pdf_wh(wo, wh) / (4.0 * Vec3::dot(wo, wh));
+    (value, pdf)
+}
+
+/// Fresnel reflectance of a dielectric, for light arriving at an angle with cosine `cos_theta` to the (micro)surface
+/// normal, where `eta` is the ratio of the index of refraction on the far side to that on the near side
+pub fn fresnel_dielectric(cos_theta: f64, eta: f64) -> f64 {
+    let cos_theta = cos_theta.clamp(0.0, 1.0);
+    let sin2_t = (1.0 - cos_theta * cos_theta) / (eta * eta);
+    if sin2_t >= 1.0 {
+        // total internal reflection
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let r_parallel = (eta * cos_theta - cos_t) / (eta * cos_theta + cos_t);
+    let r_perpendicular = (cos_theta - eta * cos_t) / (cos_theta + eta * cos_t);
+    0.5 * (r_parallel * r_parallel + r_perpendicular * r_perpendicular)
+}
+
+/// Evaluates a rough dielectric, with `wo` on the side of the surface with `eta` defined as in `fresnel_dielectric`.
+/// Returns the BSDF multiplied by the cosine term for light arriving from `wi` (either reflected or transmitted) and 
+/// leaving towards `wo`, along with the density of sampling `wi` by picking a visible normal, then reflecting
+/// or refracting `wo` about it with probability given by the Fresnel reflectance
+pub fn eval_dielectric(distribution: TrowbridgeReitz, eta: f64, wo: Vec3, wi: Vec3) -> (f64, f64) {
+    if wo.z() <= 0.0 || wi.z() == 0.0 {
+        return (0.0, 0.0);
+    }
+
+    if wi.z() > 0.0 {
+        let wh = Vec3::normalized(wo + wi);
+        let fresnel = fresnel_dielectric(Vec3::dot(wo, wh), eta);
+        let value = fresnel * distribution.d(wh) * distribution.g(wo, wi) / (4.0 * wo.z());
+        let pdf = fresnel * distribution.pdf_wh(wo, wh) / (4.0 * Vec3::dot(wo, wh));
+        (value, pdf)
+    }
+    else {
+        // generalized half vector for refraction, pointing to the same side as wo
+        let mut wh = Vec3::normalized(wo + eta * wi);
+        if wh.z() < 0.0 {
+            wh = -wh;
+        }
+        let cos_o = Vec3::dot(wo, wh);
+        let cos_i = Vec3::dot(wi, wh);
+        // both directions have to be on the correct side of the microfacet
+        if cos_o <= 0.0 || cos_i >= 0.0 {
+            return (0.0, 0.0);
+        }
+        let fresnel = fresnel_dielectric(cos_o, eta);
+        let denominator = (cos_o + eta * cos_i).powi(2);
+        // the eta^2 of the BTDF cancels with the 1 / eta^2 that radiance is scaled by crossing the surface,
+        // but it stays in the density, which is a change of variables from the half vector
+        let value = (1.0 - fresnel) * distribution.d(wh) * distribution.g(wo, wi) 
+            * cos_i.abs() * cos_o / (wo.z() * denominator);
+        let pdf = (1.0 - fresnel) * distribution.pdf_wh(wo, wh) * eta * eta * cos_i.abs() / denominator;
+        (value, pdf)
+    }
+}
@@ -1,23 +1,149 @@
 use std::{
-    sync::Arc, fmt::Debug
+    sync::Arc, fmt::Debug, f64::consts::PI
 };
 
 use rand::random;
 
 use crate::hittables::hittable::HitRecord;
 
+use self::principled::PrincipledBsdf;
+use self::microfacet::{
+    ComplexIor, 
+    TrowbridgeReitz, 
+    eval_conductor, 
+    eval_dielectric, 
+    fresnel_dielectric
+};
+
 use super::{
     ray::Ray, 
-    color::Color, 
+    color::{
+        Color, 
+        luminance
+    }, 
     vec3::{
         Vec3, 
         Point
     }, 
-    texture::Texture
+    texture::Texture, 
+    onb::Onb
 };
 
-// TODO: implement a "mix shader"
-// similar to this: https://docs.blender.org/manual/en/2.79/render/cycles/nodes/types/shaders/mix.html
+pub mod microfacet;
+pub mod principled;
+
+/// How much of the second material a `Material::Mix` uses, from 0 (only the first) to 1 (only the second)
+#[derive(Clone)]
+pub enum MixFactor {
+    Constant(f64),
+    /// Uses the luminance of the texture as the factor, so it can be driven by a mask
+    Texture(Arc<dyn Texture>)
+}
+
+impl MixFactor {
+    fn value(&self, u: f64, v: f64, p: Point) -> f64 {
+        match self {
+            MixFactor::Constant(factor) => *factor,
+            MixFactor::Texture(mask) => luminance(mask.value(u, v, p))
+        }
+    }
+}
+
+/// Detail added to a surface by perturbing its shading normal, see `Material::Perturbed`
+#[derive(Clone)]
+pub enum NormalPerturbation {
+    /// Tangent space normal map, with the usual encoding of x, y and z in red, green and blue
+    /// (so an unperturbed normal is (0.5, 0.5, 1.0)). `strength` blends from the surface normal (0)
+    /// to the mapped normal (1)
+    NormalMap {
+        texture: Arc<dyn Texture>,
+        strength: f64
+    },
+    /// Height map, the luminance of the texture is used as the height. `scale` is the height 
+    /// (in world units) a luminance of 1 corresponds to
+    BumpMap {
+        texture: Arc<dyn Texture>,
+        scale: f64
+    }
+}
+
+impl NormalPerturbation {
+    /// Step used when differentiating bump maps, in (u, v) coordinates
+    const BUMP_DELTA: f64 = 1.0 / 1024.0;
+
+    /// Replaces the normal of `record` with the perturbed shading normal
+    fn apply(&self, record: &mut HitRecord) {
+        let normal = record.normal;
+        let (dpdu, dpdv) = record.tangent_frame();
+        let perturbed = match self {
+            NormalPerturbation::NormalMap { texture, strength } => {
+                let tangent = Vec3::normalized(dpdu - Vec3::dot(dpdu, normal) * normal);
+                let mut bitangent = Vec3::cross(normal, tangent);
+                if Vec3::dot(bitangent, dpdv) < 0.0 {
+                    bitangent = -bitangent;
+                }
+                let Vec3(x, y, z) = 2.0 * texture.value(record.u, record.v, record.p) - Vec3(1.0, 1.0, 1.0);
+                let mapped = Vec3::normalized(x * tangent + y * bitangent + z * normal);
+                normal + *strength * (mapped - normal)
+            },
+            NormalPerturbation::BumpMap { texture, scale } => {
+                let delta = NormalPerturbation::BUMP_DELTA;
+                // solid textures depend on the point rather than (u, v), so it's moved along with them
+                let height = |du: f64, dv: f64| {
+                    let p = record.p + du * dpdu + dv * dpdv;
+                    *scale * luminance(texture.value(record.u + du, record.v + dv, p))
+                };
+                let base = height(0.0, 0.0);
+                let dhdu = (height(delta, 0.0) - base) / delta;
+                let dhdv = (height(0.0, delta) - base) / delta;
+
+                // displacing the surface by the height along the normal, and differentiating
+                let mut bumped = Vec3::cross(dpdu + dhdu * normal, dpdv + dhdv * normal);
+                if Vec3::dot(bumped, normal) < 0.0 {
+                    bumped = -bumped;
+                }
+                bumped
+            }
+        };
+        if !perturbed.near_zero() {
+            record.normal = Vec3::normalized(perturbed);
+        }
+    }
+}
+
+/// Absorption of light travelling through the inside of a dielectric, following the Beer-Lambert law
+/// # Fields
+/// `color` - the color white light is tinted to after travelling `distance` through the dielectric
+/// 
+/// `distance` - the distance scale `color` is measured at; thinner objects will be tinted less than this.
+/// Must be greater than 0
+#[derive(Clone, Copy, Debug)]
+pub struct Absorption {
+    pub color: Color,
+    pub distance: f64
+}
+
+impl Absorption {
+    /// Fraction of light (per channel) left after travelling `distance` through the dielectric
+    pub fn transmittance(&self, distance: f64) -> Color {
+        let exponent = distance / self.distance;
+        Vec3(self.color.0.powf(exponent), self.color.1.powf(exponent), self.color.2.powf(exponent))
+    }
+
+    /// Attenuation of a ray that has just hit a dielectric. Rays hitting the back face have been travelling
+    /// through the inside of it, and are tinted by how far they went
+    fn attenuation(absorption: Option<Absorption>, ray_in: Ray, record: &HitRecord) -> Color {
+        match absorption {
+            Some(absorption) if !record.front_face => {
+                absorption.transmittance(record.t * ray_in.direction.length())
+            }
+            _ => {
+                Vec3(1.0, 1.0, 1.0)
+            }
+        }
+    }
+}
+
 #[derive(Clone)]
 pub enum Material {
     Lambertian {
@@ -27,15 +153,57 @@ pub enum Material {
         albedo: Color,
         fuzz: f64
     },
+    /// Smooth glass; `absorption` is None for perfectly clear glass
     Dielectric {
-        index_of_refraction: f64
+        index_of_refraction: f64,
+        absorption: Option<Absorption>
+    },
+    /// Rough glass (frosted glass, ice, etc.), using a GGX microfacet distribution for both 
+    /// reflection and refraction
+    /// `roughness` - from 0 (smooth) to 1, the luminance of the texture is used
+    RoughDielectric {
+        index_of_refraction: f64,
+        roughness: Arc<dyn Texture>,
+        absorption: Option<Absorption>
     },
+    /// Rough metal, using a GGX microfacet distribution
+    /// `tint` - multiplies the Fresnel reflectance of the metal, use white for physically based results
+    /// 
+    /// `roughness` - from 0 (mirror-like) to 1, the luminance of the texture is used
+    /// 
+    /// `ior` - complex index of refraction, which determines the color of the metal
+    Conductor {
+        tint: Arc<dyn Texture>,
+        roughness: Arc<dyn Texture>,
+        ior: ComplexIor
+    },
+    /// Disney / Blender style "principled" material, see `PrincipledBsdf`
+    Principled(Box<PrincipledBsdf>),
     DiffuseLight {
         emit: Arc<dyn Texture>
     },
     Isotropic {
         albedo: Arc<dyn Texture>
-    }
+    },
+    /// Lets rays straight through, as if there were no surface. Mixed with another material it cuts holes
+    /// out of surfaces (e.g leaves out of a quad), which integrators look through rather than scatter off
+    Transparent,
+    /// Blends two materials, similar to Blender's "mix shader": 
+    /// https://docs.blender.org/manual/en/2.79/render/cycles/nodes/types/shaders/mix.html
+    /// Rather than evaluating both, one of them is picked at random (weighted by `factor`) at every hit
+    Mix {
+        first: Box<Material>,
+        second: Box<Material>,
+        factor: MixFactor
+    },
+    /// Shades `material` with a normal perturbed by a normal or bump map, using the tangents of the hit
+    Perturbed {
+        material: Box<Material>,
+        perturbation: NormalPerturbation
+    },
+    /// A material used by many surfaces, e.g a named material in a scene file or the faces of a block.
+    /// Its clones all refer to the same material, so it has one `id`
+    Shared(Arc<Material>)
 }
 
 impl Debug for Material {
@@ -44,15 +212,144 @@ impl Debug for Material {
     }
 }
 
+/// The result of a ray scattering off of a material
+/// # Fields
+/// `attenuation` - how much the scattered ray's color is attenuated by; this is the BSDF 
+/// multiplied by the cosine term, divided by `pdf`
+/// 
+/// `scattered` - the scattered ray
+/// 
+/// `pdf` - the density (with respect to solid angle) `scattered` was sampled with. None for materials 
+/// which scatter in a single direction (perfect mirrors and glass), which can't be lit by sampling lights
+/// 
+/// `kind` - what sort of bounce this was, so integrators can limit how many of each a path takes
+#[derive(Clone, Copy, Debug)]
+pub struct ScatterRecord {
+    pub attenuation: Color,
+    pub scattered: Ray,
+    pub pdf: Option<f64>,
+    pub kind: ScatterKind
+}
+
+/// The kinds of bounce a ray can take off of a material
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ScatterKind {
+    /// Scattered in every direction over the surface
+    Diffuse,
+    /// Reflected around the mirror direction, whether perfectly or by a rough (glossy) surface
+    Specular,
+    /// Refracted through the surface, by glass and the like
+    Transmission,
+    /// Scattered inside a participating medium
+    Volume
+}
+
 impl Material {
 
+    /// Wraps this material in `Shared` (unless it already is), so that it keeps one `id` however many times it's cloned
+    pub fn shared(self) -> Material {
+        match self {
+            Material::Shared(_) => self,
+            _ => Material::Shared(Arc::new(self))
+        }
+    }
+
+    /// The material inside a `Shared` one, or the material itself
+    pub fn unshared(&self) -> &Material {
+        match self {
+            Material::Shared(material) => material.unshared(),
+            _ => self
+        }
+    }
+
+    /// Tells materials apart: clones of a `Shared` material have the same id, and any other material has its own
+    pub fn id(&self) -> usize {
+        self.unshared() as *const Material as usize
+    }
+
+    /// Randomly picks one of the materials making up a `Mix`, recursively, ignoring normal perturbations. 
+    /// Every other material just returns itself
+    fn choose(&self, u: f64, v: f64, p: Point) -> &Material {
+        match self {
+            Material::Mix { first, second, factor } => {
+                if random::<f64>() < factor.value(u, v, p) {
+                    second.choose(u, v, p)
+                }
+                else {
+                    first.choose(u, v, p)
+                }
+            }
+            Material::Perturbed { material, .. } => {
+                material.choose(u, v, p)
+            }
+            Material::Shared(material) => {
+                material.choose(u, v, p)
+            }
+            _ => {
+                self
+            }
+        }
+    }
+
+    /// Picks which material is used for a hit, and applies any normal perturbations to it, updating `record`. 
+    /// This should be done once per hit, so that scattering, light sampling and emission all see the same 
+    /// material and normal
+    pub fn resolve<'a>(&'a self, record: &mut HitRecord<'a>) {
+        match self {
+            Material::Mix { first, second, factor } => {
+                if random::<f64>() < factor.value(record.u, record.v, record.p) {
+                    second.resolve(record)
+                }
+                else {
+                    first.resolve(record)
+                }
+            }
+            Material::Perturbed { material, perturbation } => {
+                perturbation.apply(record);
+                material.resolve(record)
+            }
+            Material::Shared(material) => {
+                material.resolve(record)
+            }
+            _ => {
+                record.material = self;
+            }
+        }
+    }
+
+    /// Builds a shading frame around the normal of a hit, and returns it along with the direction 
+    /// back along `ray_in` in local coordinates
+    fn shading_frame(ray_in: Ray, record: &HitRecord) -> (Onb, Vec3) {
+        let frame = Onb::build_from_w(record.normal);
+        let wo = frame.world_to_local(-Vec3::normalized(ray_in.direction));
+        (frame, wo)
+    }
+
     fn reflectance(cosine: f64, ior: f64) -> f64 {
         let mut r0 = (1.0 - ior) / (1.0 + ior);
         r0 = r0 * r0;
         r0 + (1.0 - r0) * f64::powi(1.0 - cosine, 5)
     }
 
-    pub fn scatter(&self, ray_in: Ray, record: &HitRecord) -> Option<(Color, Ray)> {
+    /// Density of `Metal` scattering in `direction`. Scattered directions are the (unit length) `reflected` direction 
+    /// plus a random point in a sphere of radius `fuzz`, so this is the fraction of that sphere's volume that 
+    /// lies along `direction`, converted into a density with respect to solid angle
+    fn fuzz_pdf(reflected: Vec3, fuzz: f64, direction: Vec3) -> f64 {
+        let projection = Vec3::dot(Vec3::normalized(direction), reflected);
+        let distance_squared = 1.0 - projection * projection;
+        if distance_squared >= fuzz * fuzz {
+            return 0.0;
+        }
+        let half_chord = (fuzz * fuzz - distance_squared).sqrt();
+        let t1 = projection + half_chord;
+        let t0 = f64::max(projection - half_chord, 0.0);
+        if t1 <= 0.0 {
+            return 0.0;
+        }
+        (t1.powi(3) - t0.powi(3)) / (4.0 * PI * fuzz.powi(3))
+    }
+
+    pub fn scatter(&self, ray_in: Ray, record: &HitRecord) -> Option<ScatterRecord> {
         match self {
             Material::Lambertian { albedo } => {
                 let mut scatter_direction = record.normal + Vec3::random_unit_vector();
@@ -62,16 +359,86 @@ impl Material {
                 }
 
                 let scattered = Ray { origin: record.p, direction: scatter_direction, ..ray_in };
-                Some((albedo.value(record.u, record.v, record.p), scattered))
+                let cosine = Vec3::dot(record.normal, Vec3::normalized(scatter_direction));
+                Some(ScatterRecord { 
+                    attenuation: albedo.value_at(record), 
+                    scattered, 
+                    pdf: Some(cosine / PI), 
+                    kind: ScatterKind::Diffuse 
+                })
             }
 
             Material::Metal { albedo, fuzz } => {
                 let reflected = Vec3::reflect(Vec3::normalized(ray_in.direction), record.normal);
                 let scattered = Ray { origin: record.p, direction: reflected + *fuzz * Vec3::random_in_unit_sphere(), ..ray_in };
-                if Vec3::dot(scattered.direction, record.normal) > 0.0 { Some((*albedo, scattered)) } else { None }
+                let pdf = if *fuzz > 0.0 { Some(Material::fuzz_pdf(reflected, *fuzz, scattered.direction)) } else { None };
+                if Vec3::dot(scattered.direction, record.normal) > 0.0 { 
+                    Some(ScatterRecord { attenuation: *albedo, scattered, pdf, kind: ScatterKind::Specular }) 
+                } 
+                else { 
+                    None 
+                }
+            }
+
+            Material::Conductor { tint, roughness, ior } => {
+                let (frame, wo) = Material::shading_frame(ray_in, record);
+                if wo.z() <= 0.0 {
+                    return None;
+                }
+                let distribution = TrowbridgeReitz::from_roughness(luminance(roughness.value_at(record)));
+                let wh = distribution.sample_wh(wo);
+                let wi = Vec3::reflect(-wo, wh);
+                let (value, pdf) = eval_conductor(distribution, *ior, wo, wi);
+                if pdf <= 0.0 {
+                    return None;
+                }
+                let scattered = Ray { origin: record.p, direction: frame.local(wi), ..ray_in };
+                Some(ScatterRecord { 
+                    attenuation: tint.value_at(record) * value / pdf, 
+                    scattered, 
+                    pdf: Some(pdf), 
+                    kind: ScatterKind::Specular 
+                })
             }
 
-            Material::Dielectric { index_of_refraction } => {
+            Material::Principled(bsdf) => {
+                let (frame, wo) = Material::shading_frame(ray_in, record);
+                if wo.z() <= 0.0 {
+                    return None;
+                }
+                let (wi, value, pdf, kind) = bsdf.sample(record, wo);
+                if pdf <= 0.0 {
+                    return None;
+                }
+                let scattered = Ray { origin: record.p, direction: frame.local(wi), ..ray_in };
+                Some(ScatterRecord { attenuation: value / pdf, scattered, pdf: Some(pdf), kind })
+            }
+
+            Material::RoughDielectric { index_of_refraction, roughness, absorption } => {
+                let (frame, wo) = Material::shading_frame(ray_in, record);
+                if wo.z() <= 0.0 {
+                    return None;
+                }
+                let eta = if record.front_face { *index_of_refraction } else { 1.0 / index_of_refraction };
+                let distribution = TrowbridgeReitz::from_roughness(luminance(roughness.value_at(record)));
+                let wh = distribution.sample_wh(wo);
+                let wi = if random::<f64>() < fresnel_dielectric(Vec3::dot(wo, wh), eta) {
+                    Vec3::reflect(-wo, wh)
+                }
+                else {
+                    Vec3::refract(-wo, wh, 1.0 / eta)
+                };
+                let (value, pdf) = eval_dielectric(distribution, eta, wo, wi);
+                if pdf <= 0.0 {
+                    return None;
+                }
+                let scattered = Ray { origin: record.p, direction: frame.local(wi), ..ray_in };
+                let attenuation = Absorption::attenuation(*absorption, ray_in, record) * (value / pdf);
+                let kind = if wi.z() < 0.0 { ScatterKind::Transmission } else { ScatterKind::Specular };
+                Some(ScatterRecord { attenuation, scattered, pdf: Some(pdf), kind })
+            }
+
+            Material::Dielectric { index_of_refraction, absorption } => {
                 let refraction_ratio = if record.front_face { 1.0 / index_of_refraction } else { *index_of_refraction };
                 let unit_direction = Vec3::normalized(ray_in.direction);
                 
@@ -80,14 +447,15 @@ impl Material {
 
                 let cannot_refract = refraction_ratio * sin_theta > 1.0;
                 
-                let direction = if cannot_refract || Material::reflectance(cos_theta, refraction_ratio) > random() {
-                    Vec3::reflect(unit_direction, record.normal)
+                let (direction, kind) = if cannot_refract || Material::reflectance(cos_theta, refraction_ratio) > random() {
+                    (Vec3::reflect(unit_direction, record.normal), ScatterKind::Specular)
                 }
                 else {                    
-                    Vec3::refract(unit_direction, record.normal, refraction_ratio)
+                    (Vec3::refract(unit_direction, record.normal, refraction_ratio), ScatterKind::Transmission)
                 };
                 let scattered = Ray { origin: record.p, direction: direction, ..ray_in };
-                Some((Vec3(1.0, 1.0, 1.0), scattered))
+                let attenuation = Absorption::attenuation(*absorption, ray_in, record);
+                Some(ScatterRecord { attenuation, scattered, pdf: None, kind })
             },
             Material::Isotropic { albedo } => {
                 let scattered = Ray {
@@ -95,19 +463,87 @@ impl Material {
                     direction: Vec3::random_in_unit_sphere(),
                     time: ray_in.time
                 };
-                let attenuation = albedo.value(record.u, record.v, record.p);
-                Some((attenuation, scattered))
+                let attenuation = albedo.value_at(record);
+                Some(ScatterRecord { attenuation, scattered, pdf: Some(1.0 / (4.0 * PI)), kind: ScatterKind::Volume })
+            },
+            Material::Transparent => {
+                let scattered = Ray { origin: record.p, ..ray_in };
+                Some(ScatterRecord { attenuation: Vec3(1.0, 1.0, 1.0), scattered, pdf: None, kind: ScatterKind::Transmission })
+            },
+            Material::Mix { .. } | Material::Perturbed { .. } | Material::Shared(_) => {
+                let mut record = *record;
+                self.resolve(&mut record);
+                record.material.scatter(ray_in, &record)
             },
             _ => {
                 None
             }
         }
     }
+
+    /// Evaluates the BSDF (multiplied by the cosine term) for light arriving from `direction` and leaving 
+    /// back along `ray_in`, along with the density `scatter` would have sampled `direction` with. 
+    /// Returns None for materials which only scatter in a single direction
+    pub fn eval(&self, ray_in: Ray, record: &HitRecord, direction: Vec3) -> Option<(Color, f64)> {
+        match self {
+            Material::Lambertian { albedo } => {
+                let cosine = Vec3::dot(record.normal, Vec3::normalized(direction)).max(0.0);
+                Some((albedo.value_at(record) * cosine / PI, cosine / PI))
+            }
+            Material::Metal { albedo, fuzz } => {
+                if *fuzz <= 0.0 {
+                    return None;
+                }
+                if Vec3::dot(direction, record.normal) <= 0.0 {
+                    return Some((Vec3(0.0, 0.0, 0.0), 0.0));
+                }
+                let reflected = Vec3::reflect(Vec3::normalized(ray_in.direction), record.normal);
+                let pdf = Material::fuzz_pdf(reflected, *fuzz, direction);
+                Some((*albedo * pdf, pdf))
+            }
+            Material::Conductor { tint, roughness, ior } => {
+                let (frame, wo) = Material::shading_frame(ray_in, record);
+                let wi = frame.world_to_local(Vec3::normalized(direction));
+                let distribution = TrowbridgeReitz::from_roughness(luminance(roughness.value_at(record)));
+                let (value, pdf) = eval_conductor(distribution, *ior, wo, wi);
+                Some((tint.value_at(record) * value, pdf))
+            }
+            Material::Principled(bsdf) => {
+                let (frame, wo) = Material::shading_frame(ray_in, record);
+                let wi = frame.world_to_local(Vec3::normalized(direction));
+                Some(bsdf.eval(record, wo, wi))
+            }
+            Material::RoughDielectric { index_of_refraction, roughness, absorption } => {
+                let (frame, wo) = Material::shading_frame(ray_in, record);
+                let wi = frame.world_to_local(Vec3::normalized(direction));
+                let eta = if record.front_face { *index_of_refraction } else { 1.0 / index_of_refraction };
+                let distribution = TrowbridgeReitz::from_roughness(luminance(roughness.value_at(record)));
+                let (value, pdf) = eval_dielectric(distribution, eta, wo, wi);
+                Some((Absorption::attenuation(*absorption, ray_in, record) * value, pdf))
+            }
+            Material::Isotropic { albedo } => {
+                let pdf = 1.0 / (4.0 * PI);
+                Some((albedo.value_at(record) * pdf, pdf))
+            }
+            Material::Mix { .. } | Material::Perturbed { .. } | Material::Shared(_) => {
+                let mut record = *record;
+                self.resolve(&mut record);
+                record.material.eval(ray_in, &record, direction)
+            }
+            _ => {
+                None
+            }
+        }
+    }
+
     pub fn emitted(&self, u: f64, v: f64, p: Point) -> Option<Color> {
         match self {
             Material::DiffuseLight { emit } => {
                 Some(emit.value(u, v, p))
             }
+            Material::Mix { .. } | Material::Perturbed { .. } | Material::Shared(_) => {
+                self.choose(u, v, p).emitted(u, v, p)
+            }
             _ => {
                 None
             }
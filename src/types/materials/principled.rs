@@ -0,0 +1,286 @@
+use std::{
+    sync::Arc,
+    f64::consts::PI
+};
+
+use rand::random;
+
+use crate::{
+    hittables::hittable::HitRecord,
+    types::{
+        vec3::Vec3,
+        color::{
+            Color,
+            luminance
+        },
+        texture::{
+            Texture,
+            SolidColor
+        }
+    }
+};
+
+use super::{
+    ScatterKind,
+    microfacet::{
+        TrowbridgeReitz,
+        eval_dielectric,
+        fresnel_dielectric
+    }
+};
+
+/// Parameters of a `Material::Principled`, loosely following the Disney "principled" BSDF
+/// (https://media.disneyanimation.com/uploads/production/publication_asset/48/asset/s2012_pbs_disney_brdf_notes_v3.pdf)
+/// in the form used by Blender and most DCC tools. Every parameter other than `base_color` and `index_of_refraction`
+/// ranges from 0 to 1, and is read from the luminance of its texture
+#[derive(Clone)]
+pub struct PrincipledBsdf {
+    pub base_color: Arc<dyn Texture>,
+    /// Blends from a dielectric (0) to a metal (1), which reflects with `base_color`
+    pub metallic: Arc<dyn Texture>,
+    pub roughness: Arc<dyn Texture>,
+    /// Strength of dielectric specular reflection, 0.5 corresponds to an index of refraction of 1.5
+    pub specular: Arc<dyn Texture>,
+    /// Tints dielectric specular reflection towards `base_color`
+    pub specular_tint: Arc<dyn Texture>,
+    /// Soft reflection at grazing angles, for cloth
+    pub sheen: Arc<dyn Texture>,
+    /// Strength of a second, clear and glossy specular layer on top
+    pub clearcoat: Arc<dyn Texture>,
+    /// Blends from opaque (0) to fully transmissive (1) for dielectrics
+    pub transmission: Arc<dyn Texture>,
+    /// Index of refraction of transmissive dielectrics
+    pub index_of_refraction: f64
+}
+
+/// Parameters of a `PrincipledBsdf` looked up at a hit
+struct Parameters {
+    base_color: Color,
+    metallic: f64,
+    roughness: f64,
+    specular: f64,
+    specular_tint: f64,
+    sheen: f64,
+    clearcoat: f64,
+    transmission: f64,
+    eta: f64
+}
+
+const CLEARCOAT_ROUGHNESS: f64 = 0.1;
+const SHEEN_TINT: f64 = 0.5;
+
+fn constant(x: f64) -> Arc<dyn Texture> {
+    Arc::new(SolidColor::from(Vec3(x, x, x)))
+}
+
+fn mix(a: Color, b: Color, t: f64) -> Color {
+    (1.0 - t) * a + t * b
+}
+
+fn schlick_weight(cosine: f64) -> f64 {
+    (1.0 - cosine).clamp(0.0, 1.0).powi(5)
+}
+
+/// Which part of the BSDF a direction is sampled from
+#[derive(Clone, Copy)]
+enum Lobe {
+    Diffuse,
+    Specular,
+    Clearcoat,
+    Transmission
+}
+
+impl Parameters {
+    /// `base_color` with its luminance normalized, used to tint other lobes by hue only
+    fn tint(&self) -> Color {
+        let lum = luminance(self.base_color);
+        if lum > 0.0 { self.base_color / lum } else { Vec3(1.0, 1.0, 1.0) }
+    }
+
+    /// Reflectance of the specular lobe at normal incidence
+    fn specular_f0(&self) -> Color {
+        let dielectric = 0.08 * self.specular * mix(Vec3(1.0, 1.0, 1.0), self.tint(), self.specular_tint);
+        mix(dielectric, self.base_color, self.metallic)
+    }
+
+    fn diffuse_weight(&self) -> f64 {
+        (1.0 - self.metallic) * (1.0 - self.transmission)
+    }
+
+    /// The transmission lobe includes its own (dielectric) reflection, so the specular lobe fades out as it fades in
+    fn specular_weight(&self) -> f64 {
+        1.0 - (1.0 - self.metallic) * self.transmission
+    }
+
+    fn transmission_weight(&self) -> f64 {
+        (1.0 - self.metallic) * self.transmission
+    }
+
+    /// Probability of sampling each lobe, roughly proportional to how much light it reflects
+    fn lobe_probabilities(&self) -> [(Lobe, f64); 4] {
+        let diffuse = self.diffuse_weight() * luminance(self.base_color);
+        let specular = self.specular_weight() * f64::max(luminance(self.specular_f0()), 0.1);
+        let clearcoat = 0.25 * self.clearcoat;
+        let transmission = self.transmission_weight();
+        let total = diffuse + specular + clearcoat + transmission;
+        [
+            (Lobe::Diffuse, diffuse / total),
+            (Lobe::Specular, specular / total),
+            (Lobe::Clearcoat, clearcoat / total),
+            (Lobe::Transmission, transmission / total)
+        ]
+    }
+
+    /// Burley's diffuse with retroreflection, plus sheen
+    fn eval_diffuse(&self, wo: Vec3, wi: Vec3) -> (Color, f64) {
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return (Vec3(0.0, 0.0, 0.0), 0.0);
+        }
+        let wh = Vec3::normalized(wo + wi);
+        let cos_d = Vec3::dot(wi, wh);
+        let fd90 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+        let fd = (1.0 + (fd90 - 1.0) * schlick_weight(wi.z())) * (1.0 + (fd90 - 1.0) * schlick_weight(wo.z()));
+        let sheen = self.sheen * schlick_weight(cos_d) * mix(Vec3(1.0, 1.0, 1.0), self.tint(), SHEEN_TINT);
+        let value = (self.base_color * fd / PI + sheen) * self.diffuse_weight() * wi.z();
+        (value, wi.z() / PI)
+    }
+
+    /// GGX reflection with Schlick's Fresnel approximation, used for both the specular and clearcoat lobes
+    fn eval_reflection(distribution: TrowbridgeReitz, f0: Color, wo: Vec3, wi: Vec3) -> (Color, f64) {
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return (Vec3(0.0, 0.0, 0.0), 0.0);
+        }
+        let wh = Vec3::normalized(wo + wi);
+        let fresnel = f0 + (Vec3(1.0, 1.0, 1.0) - f0) * schlick_weight(Vec3::dot(wi, wh));
+        let value = distribution.d(wh) * distribution.g(wo, wi) * fresnel / (4.0 * wo.z());
+        let pdf = distribution.pdf_wh(wo, wh) / (4.0 * Vec3::dot(wo, wh));
+        (value, pdf)
+    }
+
+    fn eval_lobe(&self, lobe: Lobe, wo: Vec3, wi: Vec3) -> (Color, f64) {
+        match lobe {
+            Lobe::Diffuse => self.eval_diffuse(wo, wi),
+            Lobe::Specular => {
+                let distribution = TrowbridgeReitz::from_roughness(self.roughness);
+                let (value, pdf) = Parameters::eval_reflection(distribution, self.specular_f0(), wo, wi);
+                (value * self.specular_weight(), pdf)
+            },
+            Lobe::Clearcoat => {
+                let distribution = TrowbridgeReitz::from_roughness(CLEARCOAT_ROUGHNESS);
+                let (value, pdf) = Parameters::eval_reflection(distribution, Vec3(0.04, 0.04, 0.04), wo, wi);
+                (value * 0.25 * self.clearcoat, pdf)
+            },
+            Lobe::Transmission => {
+                let distribution = TrowbridgeReitz::from_roughness(self.roughness);
+                let (value, pdf) = eval_dielectric(distribution, self.eta, wo, wi);
+                (self.base_color * value * self.transmission_weight(), pdf)
+            }
+        }
+    }
+
+    fn sample_lobe(&self, lobe: Lobe, wo: Vec3) -> Vec3 {
+        match lobe {
+            Lobe::Diffuse => {
+                Vec3::normalized(Vec3(0.0, 0.0, 1.0) + Vec3::random_unit_vector())
+            },
+            Lobe::Specular => {
+                let wh = TrowbridgeReitz::from_roughness(self.roughness).sample_wh(wo);
+                Vec3::reflect(-wo, wh)
+            },
+            Lobe::Clearcoat => {
+                let wh = TrowbridgeReitz::from_roughness(CLEARCOAT_ROUGHNESS).sample_wh(wo);
+                Vec3::reflect(-wo, wh)
+            },
+            Lobe::Transmission => {
+                let wh = TrowbridgeReitz::from_roughness(self.roughness).sample_wh(wo);
+                if random::<f64>() < fresnel_dielectric(Vec3::dot(wo, wh), self.eta) {
+                    Vec3::reflect(-wo, wh)
+                }
+                else {
+                    Vec3::refract(-wo, wh, 1.0 / self.eta)
+                }
+            }
+        }
+    }
+
+    fn eval(&self, wo: Vec3, wi: Vec3) -> (Color, f64) {
+        let mut value = Vec3(0.0, 0.0, 0.0);
+        let mut pdf = 0.0;
+        for (lobe, probability) in self.lobe_probabilities() {
+            if probability > 0.0 {
+                let (lobe_value, lobe_pdf) = self.eval_lobe(lobe, wo, wi);
+                value += lobe_value;
+                pdf += probability * lobe_pdf;
+            }
+        }
+        (value, pdf)
+    }
+}
+
+impl PrincipledBsdf {
+    /// Creates a rough, non-metallic material with the given base color and otherwise default parameters
+    pub fn new(base_color: Arc<dyn Texture>) -> PrincipledBsdf {
+        PrincipledBsdf {
+            base_color,
+            metallic: constant(0.0),
+            roughness: constant(0.5),
+            specular: constant(0.5),
+            specular_tint: constant(0.0),
+            sheen: constant(0.0),
+            clearcoat: constant(0.0),
+            transmission: constant(0.0),
+            index_of_refraction: 1.45
+        }
+    }
+
+    fn parameters(&self, record: &HitRecord) -> Parameters {
+        let scalar = |texture: &Arc<dyn Texture>| luminance(texture.value_at(record)).clamp(0.0, 1.0);
+        Parameters {
+            base_color: self.base_color.value_at(record),
+            metallic: scalar(&self.metallic),
+            roughness: scalar(&self.roughness),
+            specular: scalar(&self.specular),
+            specular_tint: scalar(&self.specular_tint),
+            sheen: scalar(&self.sheen),
+            clearcoat: scalar(&self.clearcoat),
+            transmission: scalar(&self.transmission),
+            eta: if record.front_face { self.index_of_refraction } else { 1.0 / self.index_of_refraction }
+        }
+    }
+
+    /// Returns the BSDF multiplied by the cosine term for light arriving from `wi` and leaving towards `wo`,
+    /// along with the density `sample` would have returned `wi` with. Directions are in the local shading frame
+    pub fn eval(&self, record: &HitRecord, wo: Vec3, wi: Vec3) -> (Color, f64) {
+        self.parameters(record).eval(wo, wi)
+    }
+
+    /// Picks one of the lobes at random, and samples a direction `wi` from it. Returns `wi`, along with the
+    /// value and density of the whole BSDF as in `eval`, and the kind of bounce the lobe made
+    pub fn sample(&self, record: &HitRecord, wo: Vec3) -> (Vec3, Color, f64, ScatterKind) {
+        let parameters = self.parameters(record);
+        let probabilities = parameters.lobe_probabilities();
+
+        let mut choice = random::<f64>();
+        let mut lobe = probabilities[0].0;
+        for (candidate, probability) in probabilities {
+            if probability > 0.0 {
+                lobe = candidate;
+                if choice < probability {
+                    break;
+                }
+                choice -= probability;
+            }
+        }
+
+        let wi = parameters.sample_lobe(lobe, wo);
+        let (value, pdf) = parameters.eval(wo, wi);
+        let kind = match lobe {
+            Lobe::Diffuse => ScatterKind::Diffuse,
+            Lobe::Specular | Lobe::Clearcoat => ScatterKind::Specular,
+            // the transmission lobe reflects some of the time too
+            Lobe::Transmission if wi.z() < 0.0 => ScatterKind::Transmission,
+            Lobe::Transmission => ScatterKind::Specular
+        };
+        (wi, value, pdf, kind)
+    }
+}
@@ -6,4 +6,7 @@ pub mod aabb;
 pub mod bvh;
 pub mod texture;
 pub mod transform;
-pub mod vec2;
\ No newline at end of file
+pub mod vec2;
+pub mod onb;
+pub mod environment_map;
+pub mod sky;
\ No newline at end of file
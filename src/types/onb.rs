@@ -0,0 +1,31 @@
+use super::vec3::Vec3;
+
+/// Orthonormal basis, used to move directions generated around the z-axis
+/// into a coordinate system where `w` is "up" (e.g a surface normal)
+#[derive(Clone, Copy, Debug)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3
+}
+
+impl Onb {
+    /// Construct an orthonormal basis around `n`, which does not need to be normalized
+    pub fn build_from_w(n: Vec3) -> Onb {
+        let w = Vec3::normalized(n);
+        let a = if w.x().abs() > 0.9 { Vec3(0.0, 1.0, 0.0) } else { Vec3(1.0, 0.0, 0.0) };
+        let v = Vec3::normalized(Vec3::cross(w, a));
+        let u = Vec3::cross(w, v);
+        Onb { u, v, w }
+    }
+
+    /// Convert a vector from local coordinates into the coordinate system this basis is in
+    pub fn local(&self, a: Vec3) -> Vec3 {
+        a.x() * self.u + a.y() * self.v + a.z() * self.w
+    }
+
+    /// Convert a vector into local coordinates, the inverse of `local`
+    pub fn world_to_local(&self, a: Vec3) -> Vec3 {
+        Vec3(Vec3::dot(a, self.u), Vec3::dot(a, self.v), Vec3::dot(a, self.w))
+    }
+}
@@ -0,0 +1,188 @@
+use std::f64::consts::PI;
+
+use rand::random;
+
+use super::{
+    vec3::Vec3,
+    color::Color,
+    onb::Onb
+};
+
+/// Angular radius of the sun seen from the ground, in radians
+const SUN_ANGULAR_RADIUS: f64 = 0.004_65;
+
+/// Luminance of the sun before it passes through the atmosphere, in kcd/m^2 (the unit the sky is in)
+const SUN_LUMINANCE: f64 = 2.0e6;
+
+/// Radiance of 1.0 in the renderer is this many kcd/m^2, which puts a white surface in the midday sun at around 1
+const LUMINANCE_SCALE: f64 = 1.0 / 30.0;
+
+/// Wavelengths (in micrometers) the red, green and blue channels of the sun are attenuated at
+const WAVELENGTHS: [f64; 3] = [0.65, 0.55, 0.45];
+
+/// The clear sky model of Preetham, Shirley and Smits ("A Practical Analytic Model for Daylight", 1999), along with a
+/// sun disk which is sampled directly as a light. The world is laid out with +y up, -z north and +x east
+/// # Fields
+/// `turbidity` - haziness of the atmosphere, from 2 (very clear) to around 10 (hazy)
+///
+/// `intensity` - scales both the sky and the sun
+///
+/// `perez` - coefficients A to E of the Perez distribution for luminance (Y) and the x and y chromaticities
+///
+/// `zenith` - luminance and chromaticities straight up, in the same order
+///
+/// `sun_radiance` - radiance of the sun disk, reddened by the air it shines through. Black once the sun has set
+pub struct PhysicalSky {
+    sun_direction: Vec3,
+    turbidity: f64,
+    intensity: f64,
+    perez: [[f64; 5]; 3],
+    zenith: [f64; 3],
+    sun_radiance: Color,
+    cos_sun_radius: f64
+}
+
+impl std::fmt::Debug for PhysicalSky {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PhysicalSky")
+            .field("sun_direction", &self.sun_direction)
+            .field("turbidity", &self.turbidity)
+            .field("intensity", &self.intensity)
+            .finish()
+    }
+}
+
+impl PhysicalSky {
+    /// Sky lit by a sun in `sun_direction` (which doesn't need to be normalized). The model only holds with the sun
+    /// above the horizon - below it the sky is kept as it was at sunset, and the sun disk is left out
+    pub fn new(sun_direction: Vec3, turbidity: f64, intensity: f64) -> PhysicalSky {
+        let sun_direction = Vec3::normalized(sun_direction);
+        let theta_sun = sun_direction.y().clamp(0.0, 1.0).acos();
+        let t = turbidity;
+
+        let perez = [
+            [0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251, 0.1206 * t - 2.5771, -0.0670 * t + 0.3703],
+            [-0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125, -0.0641 * t - 0.8989, -0.0033 * t + 0.0452],
+            [-0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102, -0.0441 * t - 1.6537, -0.0109 * t + 0.0529]
+        ];
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_sun);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let (theta, theta2, theta3) = (theta_sun, theta_sun * theta_sun, theta_sun * theta_sun * theta_sun);
+        let zenith_x = t * t * (0.00166 * theta3 - 0.00375 * theta2 + 0.00209 * theta)
+            + t * (-0.02903 * theta3 + 0.06377 * theta2 - 0.03202 * theta + 0.00394)
+            + (0.11693 * theta3 - 0.21196 * theta2 + 0.06052 * theta + 0.25886);
+        let zenith_y = t * t * (0.00275 * theta3 - 0.00610 * theta2 + 0.00317 * theta)
+            + t * (-0.04214 * theta3 + 0.08970 * theta2 - 0.04153 * theta + 0.00516)
+            + (0.15346 * theta3 - 0.26756 * theta2 + 0.06670 * theta + 0.26688);
+
+        let sun_radiance = if sun_direction.y() > 0.0 {
+            SUN_LUMINANCE * LUMINANCE_SCALE * intensity * sun_transmittance(theta_sun, turbidity)
+        }
+        else {
+            Vec3(0.0, 0.0, 0.0)
+        };
+
+        PhysicalSky {
+            sun_direction,
+            turbidity,
+            intensity,
+            perez,
+            zenith: [zenith_luminance, zenith_x, zenith_y],
+            sun_radiance,
+            cos_sun_radius: SUN_ANGULAR_RADIUS.cos()
+        }
+    }
+
+    /// Direction of the sun seen from `latitude` and `longitude` (in degrees, north and east are positive), on
+    /// day `day_of_year` (1 is January 1st) at `time` hours on a clock `timezone` hours ahead of UTC.
+    /// Uses the approximations from the appendix of Preetham et al., which are good to within a degree or so
+    pub fn sun_direction_at(latitude: f64, longitude: f64, day_of_year: f64, time: f64, timezone: f64) -> Vec3 {
+        let latitude = latitude.to_radians();
+        let solar_time = time
+            + 0.170 * (4.0 * PI * (day_of_year - 80.0) / 373.0).sin()
+            - 0.129 * (2.0 * PI * (day_of_year - 8.0) / 355.0).sin()
+            + (longitude - 15.0 * timezone) / 15.0;
+        let declination = 0.4093 * (2.0 * PI * (day_of_year - 81.0) / 368.0).sin();
+        let hour_angle = PI * (solar_time - 12.0) / 12.0;
+
+        let elevation = (latitude.sin() * declination.sin() + latitude.cos() * declination.cos() * hour_angle.cos()).asin();
+        // clockwise from north, so east is 90 degrees
+        let azimuth = f64::atan2(
+            -declination.cos() * hour_angle.sin(),
+            declination.sin() * latitude.cos() - declination.cos() * latitude.sin() * hour_angle.cos()
+        );
+        Vec3(elevation.cos() * azimuth.sin(), elevation.sin(), -elevation.cos() * azimuth.cos())
+    }
+
+    /// Whether the sun is up, and so can be sampled
+    pub fn has_sun(&self) -> bool {
+        self.sun_direction.y() > 0.0
+    }
+
+    /// Radiance arriving from `direction` (which doesn't need to be normalized), including the sun disk.
+    /// Below the horizon the sky is continued with its color at the horizon
+    pub fn radiance(&self, direction: Vec3) -> Color {
+        let direction = Vec3::normalized(direction);
+        let cos_gamma = Vec3::dot(direction, self.sun_direction).clamp(-1.0, 1.0);
+        let sun = if self.has_sun() && cos_gamma >= self.cos_sun_radius { self.sun_radiance } else { Vec3(0.0, 0.0, 0.0) };
+
+        // the distribution blows up at the horizon, so stop just short of it
+        let theta = direction.y().clamp(0.001, 1.0).acos();
+        let gamma = cos_gamma.acos();
+        let theta_sun = self.sun_direction.y().clamp(0.0, 1.0).acos();
+        let [luminance, x, y] = [0, 1, 2].map(|i| {
+            self.zenith[i] * perez(&self.perez[i], theta, gamma) / perez(&self.perez[i], 0.0, theta_sun)
+        });
+
+        sun + self.intensity * LUMINANCE_SCALE * xy_luminance_to_rgb(x, y, luminance.max(0.0))
+    }
+
+    /// Picks a random (unit) direction towards the sun disk
+    pub fn random(&self) -> Vec3 {
+        let cos_theta = 1.0 - random::<f64>() * (1.0 - self.cos_sun_radius);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * random::<f64>();
+        Onb::build_from_w(self.sun_direction).local(Vec3(phi.cos() * sin_theta, phi.sin() * sin_theta, cos_theta))
+    }
+
+    /// Probability density (with respect to solid angle) of `random` returning `direction`
+    pub fn pdf_value(&self, direction: Vec3) -> f64 {
+        if self.has_sun() && Vec3::dot(Vec3::normalized(direction), self.sun_direction) >= self.cos_sun_radius {
+            1.0 / (2.0 * PI * (1.0 - self.cos_sun_radius))
+        }
+        else {
+            0.0
+        }
+    }
+}
+
+/// The Perez sky distribution, `theta` being the angle from the zenith and `gamma` the angle from the sun
+fn perez(coefficients: &[f64; 5], theta: f64, gamma: f64) -> f64 {
+    let [a, b, c, d, e] = *coefficients;
+    (1.0 + a * (b / theta.cos()).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos() * gamma.cos())
+}
+
+/// Converts CIE xyY to linear sRGB, clipping colors outside of it
+fn xy_luminance_to_rgb(x: f64, y: f64, luminance: f64) -> Color {
+    let big_x = x * luminance / y;
+    let big_z = (1.0 - x - y) * luminance / y;
+    Vec3(
+        (3.2406 * big_x - 1.5372 * luminance - 0.4986 * big_z).max(0.0),
+        (-0.9689 * big_x + 1.8758 * luminance + 0.0415 * big_z).max(0.0),
+        (0.0557 * big_x - 0.2040 * luminance + 1.0570 * big_z).max(0.0)
+    )
+}
+
+/// Fraction of the sun's light in each channel which makes it through the atmosphere, when the sun is `theta_sun`
+/// radians from the zenith. Only Rayleigh and aerosol scattering are accounted for
+fn sun_transmittance(theta_sun: f64, turbidity: f64) -> Color {
+    let relative_air_mass = 1.0 / (theta_sun.cos() + 0.15 * (93.885 - theta_sun.to_degrees()).powf(-1.253));
+    let beta = 0.04608 * turbidity - 0.04586;
+    let [r, g, b] = WAVELENGTHS.map(|lambda: f64| {
+        let rayleigh = -0.008735 * lambda.powf(-4.08) * relative_air_mass;
+        let aerosol = -beta * lambda.powf(-1.3) * relative_air_mass;
+        (rayleigh + aerosol).exp()
+    });
+    Vec3(r, g, b)
+}
\ No newline at end of file
@@ -1,11 +1,17 @@
+use std::path::Path;
+
 use image::{
     io::Reader as ImageReader, 
     DynamicImage, 
-    GenericImageView
+    GenericImageView, 
+    ImageError
 };
 
-use crate::utils::{
-    perlin::Perlin
+use crate::{
+    utils::{
+        perlin::Perlin
+    }, 
+    hittables::hittable::HitRecord
 };
 
 use super::{
@@ -20,6 +26,12 @@ use super::{
 
 pub trait Texture: Send + Sync {
     fn value(&self, u: f64, v: f64, p: Point) -> Color;
+
+    /// Value of the texture where `record` hit. Textures that depend on more than the uv coordinates
+    /// and the point (like vertex colors) override this, everything else just looks up `value`
+    fn value_at(&self, record: &HitRecord) -> Color {
+        self.value(record.u, record.v, record.p)
+    }
 }
 
 pub struct SolidColor {
@@ -102,15 +114,42 @@ impl Texture for ImageTexture {
     }
 }
 
-impl From<&str> for ImageTexture {
-    fn from(filename: &str) -> ImageTexture {
-        let img = ImageReader::open(filename).expect("failed to open texture file");
-        let img = img.decode().expect("failed to decode texture file");
+impl ImageTexture {
+    pub fn open(path: &Path) -> Result<ImageTexture, ImageError> {
+        let img = ImageReader::open(path)?.decode()?;
 
-        ImageTexture {  
+        Ok(ImageTexture {  
             width: img.width(),
             height: img.height(),
             data: img
-        }
+        })
+    }
+}
+
+impl From<&str> for ImageTexture {
+    fn from(filename: &str) -> ImageTexture {
+        ImageTexture::open(Path::new(filename)).expect("failed to load texture file")
+    }
+}
+
+/// Reads the color interpolated from the vertices of the mesh that was hit.
+/// Anything without vertex colors falls back to `fallback`
+pub struct VertexColorTexture {
+    fallback: Color
+}
+
+impl Texture for VertexColorTexture {
+    fn value(&self, _u: f64, _v: f64, _p: Point) -> Color {
+        self.fallback
+    }
+
+    fn value_at(&self, record: &HitRecord) -> Color {
+        record.vertex_color.unwrap_or(self.fallback)
+    }
+}
+
+impl VertexColorTexture {
+    pub fn new(fallback: Color) -> VertexColorTexture {
+        VertexColorTexture { fallback }
     }
 }
\ No newline at end of file
@@ -1,4 +1,4 @@
-use super::{vec3::Vec3, ray::Ray};
+use super::{vec3::Vec3, ray::Ray, aabb::AABB};
 
 // Very inefficient code for handling transformation matrices
 
@@ -25,6 +25,70 @@ impl Quaternion {
         TransformData { data: self.get_transformation_matrix(), inverse: self.inv().get_transformation_matrix() }
     }
 
+    /// Rotation described by the (orthonormal, non-reflecting) upper left 3x3 of `matrix`
+    fn from_rotation_matrix(matrix: Matrix4x4) -> Quaternion {
+        let m = matrix;
+        let trace = m[0][0] + m[1][1] + m[2][2];
+        if trace > 0.0 {
+            let s = 0.5 / (trace + 1.0).sqrt();
+            Quaternion { a: 0.25 / s, b: (m[2][1] - m[1][2]) * s, c: (m[0][2] - m[2][0]) * s, d: (m[1][0] - m[0][1]) * s }
+        }
+        else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+            let s = 2.0 * (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt();
+            Quaternion { a: (m[2][1] - m[1][2]) / s, b: 0.25 * s, c: (m[0][1] + m[1][0]) / s, d: (m[0][2] + m[2][0]) / s }
+        }
+        else if m[1][1] > m[2][2] {
+            let s = 2.0 * (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt();
+            Quaternion { a: (m[0][2] - m[2][0]) / s, b: (m[0][1] + m[1][0]) / s, c: 0.25 * s, d: (m[1][2] + m[2][1]) / s }
+        }
+        else {
+            let s = 2.0 * (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt();
+            Quaternion { a: (m[1][0] - m[0][1]) / s, b: (m[0][2] + m[2][0]) / s, c: (m[1][2] + m[2][1]) / s, d: 0.25 * s }
+        }
+    }
+
+    fn dot(p: Quaternion, q: Quaternion) -> f64 {
+        p.a * q.a + p.b * q.b + p.c * q.c + p.d * q.d
+    }
+
+    /// Angle (in radians) of the rotation from `p` to `q`, taking the shorter way around
+    fn angle_between(p: Quaternion, q: Quaternion) -> f64 {
+        let cosine = Quaternion::dot(p, q).abs() / (p.square_magnitude() * q.square_magnitude()).sqrt();
+        2.0 * cosine.min(1.0).acos()
+    }
+
+    /// Spherical linear interpolation - rotates from `p` to `q` at a constant angular velocity as `t` goes from 0 to 1,
+    /// taking the shorter way around
+    pub fn slerp(p: Quaternion, q: Quaternion, t: f64) -> Quaternion {
+        let p = p.normalized();
+        let mut q = q.normalized();
+        let mut cosine = Quaternion::dot(p, q);
+        if cosine < 0.0 {
+            q = Quaternion { a: -q.a, b: -q.b, c: -q.c, d: -q.d };
+            cosine = -cosine;
+        }
+
+        let (weight_p, weight_q) = if cosine > 0.9995 {
+            // nearly parallel, so a plain lerp is accurate and avoids dividing by sin(theta) ~ 0
+            (1.0 - t, t)
+        }
+        else {
+            let theta = cosine.acos();
+            (((1.0 - t) * theta).sin() / theta.sin(), (t * theta).sin() / theta.sin())
+        };
+        Quaternion {
+            a: weight_p * p.a + weight_q * q.a,
+            b: weight_p * p.b + weight_q * q.b,
+            c: weight_p * p.c + weight_q * q.c,
+            d: weight_p * p.d + weight_q * q.d
+        }.normalized()
+    }
+
+    fn normalized(&self) -> Quaternion {
+        let magnitude = self.square_magnitude().sqrt();
+        Quaternion { a: self.a / magnitude, b: self.b / magnitude, c: self.c / magnitude, d: self.d / magnitude }
+    }
+
     fn inv(&self) -> Quaternion {
         let square_magnitude = self.square_magnitude();
         let a = self.a / square_magnitude;
@@ -60,7 +124,8 @@ impl Quaternion {
 #[derive(Clone, Copy)]
 struct Vec4(f64, f64, f64, f64);
 
-type Matrix4x4 = [[f64; 4]; 4];
+/// Row major, so the translation is in the last column
+pub type Matrix4x4 = [[f64; 4]; 4];
 
 impl TransformData {
     pub fn identity() -> TransformData {
@@ -83,6 +148,50 @@ impl TransformData {
         self.compose(translation_matrix)
     }
 
+    /// Scales uniformly about the origin. `factor` must not be 0
+    pub fn scale(&self, factor: f64) -> TransformData {
+        self.scale_non_uniform(Vec3(factor, factor, factor))
+    }
+
+    /// Scales about the origin by a different factor along each axis. None of the factors may be 0
+    pub fn scale_non_uniform(&self, factors: Vec3) -> TransformData {
+        assert!(factors.0 != 0.0 && factors.1 != 0.0 && factors.2 != 0.0, "cannot scale by 0");
+        let mut scale_matrix = TransformData::identity();
+        scale_matrix.data[0][0] = factors.0;
+        scale_matrix.data[1][1] = factors.1;
+        scale_matrix.data[2][2] = factors.2;
+        scale_matrix.inverse[0][0] = 1.0 / factors.0;
+        scale_matrix.inverse[1][1] = 1.0 / factors.1;
+        scale_matrix.inverse[2][2] = 1.0 / factors.2;
+        self.compose(scale_matrix)
+    }
+
+    /// Shears each axis by the others, e.g `xy` is how far x moves for each unit of y.
+    /// Returns None if the shear flattens space (and so can't be inverted)
+    pub fn shear(&self, xy: f64, xz: f64, yx: f64, yz: f64, zx: f64, zy: f64) -> Option<TransformData> {
+        let mut shear_matrix = [[0.0; 4]; 4];
+        shear_matrix[0] = [1.0, xy, xz, 0.0];
+        shear_matrix[1] = [yx, 1.0, yz, 0.0];
+        shear_matrix[2] = [zx, zy, 1.0, 0.0];
+        shear_matrix[3][3] = 1.0;
+        self.apply_matrix(shear_matrix)
+    }
+
+    /// Applies an arbitrary matrix after this transform. Returns None if the matrix can't be inverted
+    pub fn apply_matrix(&self, matrix: Matrix4x4) -> Option<TransformData> {
+        Some(self.compose(TransformData::from_matrix(matrix)?))
+    }
+
+    /// Transform with the given matrix, or None if the matrix can't be inverted
+    pub fn from_matrix(matrix: Matrix4x4) -> Option<TransformData> {
+        Some(TransformData { data: matrix, inverse: TransformData::invert(matrix)? })
+    }
+
+    /// Swaps the forward and inverse directions of this transform
+    pub fn inverse(&self) -> TransformData {
+        TransformData { data: self.inverse, inverse: self.data }
+    }
+
     pub fn rotate_euler(&self, rotation: Vec3) -> TransformData {
         self.rotate_angle_axis(Vec3(1.0, 0.0, 0.0), rotation.0)
             .rotate_angle_axis(Vec3(0.0, 1.0, 0.0), rotation.1)
@@ -114,6 +223,96 @@ impl TransformData {
         }
     }
 
+    /// Inverts `matrix` by Gauss-Jordan elimination with partial pivoting,
+    /// or returns None if it's singular
+    fn invert(matrix: Matrix4x4) -> Option<Matrix4x4> {
+        let mut matrix = matrix;
+        let mut inverse = TransformData::identity().data;
+        for column in 0..4 {
+            let pivot = (column..4)
+                .max_by(|&a, &b| matrix[a][column].abs().total_cmp(&matrix[b][column].abs()))
+                .expect("there is always a row to pivot on");
+            if matrix[pivot][column].abs() < 1e-12 {
+                return None;
+            }
+            matrix.swap(column, pivot);
+            inverse.swap(column, pivot);
+
+            let scale = 1.0 / matrix[column][column];
+            for j in 0..4 {
+                matrix[column][j] *= scale;
+                inverse[column][j] *= scale;
+            }
+            for row in 0..4 {
+                if row == column {
+                    continue;
+                }
+                let factor = matrix[row][column];
+                for j in 0..4 {
+                    matrix[row][j] -= factor * matrix[column][j];
+                    inverse[row][j] -= factor * inverse[column][j];
+                }
+            }
+        }
+        Some(inverse)
+    }
+
+    /// Inverts a matrix without translation by its adjugate, which is much cheaper than `invert`
+    /// but assumes the matrix isn't singular
+    fn invert_linear(m: Matrix4x4) -> Matrix4x4 {
+        let mut inverse = TransformData::identity().inverse;
+        inverse[0][0] = m[1][1] * m[2][2] - m[1][2] * m[2][1];
+        inverse[0][1] = m[0][2] * m[2][1] - m[0][1] * m[2][2];
+        inverse[0][2] = m[0][1] * m[1][2] - m[0][2] * m[1][1];
+        inverse[1][0] = m[1][2] * m[2][0] - m[1][0] * m[2][2];
+        inverse[1][1] = m[0][0] * m[2][2] - m[0][2] * m[2][0];
+        inverse[1][2] = m[0][2] * m[1][0] - m[0][0] * m[1][2];
+        inverse[2][0] = m[1][0] * m[2][1] - m[1][1] * m[2][0];
+        inverse[2][1] = m[0][1] * m[2][0] - m[0][0] * m[2][1];
+        inverse[2][2] = m[0][0] * m[1][1] - m[0][1] * m[1][0];
+        let determinant = m[0][0] * inverse[0][0] + m[0][1] * inverse[1][0] + m[0][2] * inverse[2][0];
+        for row in inverse.iter_mut().take(3) {
+            for value in row.iter_mut().take(3) {
+                *value /= determinant;
+            }
+        }
+        inverse
+    }
+
+    /// Transforms a direction, which unlike a point isn't affected by translation
+    pub fn vector(&self, vector: Vec3) -> Vec3 {
+        TransformData::apply_linear(self.data, vector)
+    }
+
+    pub fn inverse_vector(&self, vector: Vec3) -> Vec3 {
+        TransformData::apply_linear(self.inverse, vector)
+    }
+
+    /// Transforms a surface normal. Normals are multiplied by the inverse transpose so they stay
+    /// perpendicular to the (transformed) surface under non-uniform scaling and shear. Not normalized
+    pub fn normal(&self, normal: Vec3) -> Vec3 {
+        let m = self.inverse;
+        Vec3(
+            m[0][0] * normal.0 + m[1][0] * normal.1 + m[2][0] * normal.2,
+            m[0][1] * normal.0 + m[1][1] * normal.1 + m[2][1] * normal.2,
+            m[0][2] * normal.0 + m[1][2] * normal.1 + m[2][2] * normal.2
+        )
+    }
+
+    /// Determinant of the linear (non-translation) part of the inverse transform, which
+    /// is how much it scales volumes by
+    pub fn inverse_determinant(&self) -> f64 {
+        let m = self.inverse;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    fn apply_linear(matrix: Matrix4x4, vector: Vec3) -> Vec3 {
+        let transformed = TransformData::apply_to_vec4(matrix, Vec4(vector.0, vector.1, vector.2, 0.0));
+        Vec3(transformed.0, transformed.1, transformed.2)
+    }
+
     pub fn matmul(a: Matrix4x4, b: Matrix4x4) -> Matrix4x4 {
         let mut data = [[0.0; 4]; 4];
         for i in 0..4 {
@@ -195,4 +394,172 @@ impl InverseTransform<Ray> for Ray {
         let transformed_direction = (self.origin + self.direction).inverse_transform(transform) - transformed_origin;
         Ray { origin: transformed_origin, direction: transformed_direction, time: self.time }
     }
+}
+
+/// A keyframe of an `AnimatedTransform`, split into translation, rotation and stretch
+/// (any scaling or shear) so that each can be interpolated separately. `transform` is the whole
+/// keyframe, kept for times before the first keyframe or after the last
+#[derive(Clone, Copy)]
+struct Keyframe {
+    time: f64,
+    translation: Vec3,
+    rotation: Quaternion,
+    stretch: TransformData,
+    transform: TransformData
+}
+
+/// A transform which changes over time, for motion blur. Between keyframes the translation and stretch
+/// are linearly interpolated, and the rotation is interpolated with `Quaternion::slerp`.
+/// Before the first keyframe and after the last, the transform stays at that keyframe
+#[derive(Clone)]
+pub struct AnimatedTransform {
+    keyframes: Vec<Keyframe>
+}
+
+/// Number of times the motion between each pair of keyframes is sampled when bounding it
+const BOUNDING_STEPS: usize = 64;
+
+impl AnimatedTransform {
+    /// Create an AnimatedTransform from (time, transform) pairs, which don't need to be in order.
+    /// There must be at least one keyframe. Returns None if a mirrored keyframe is next to one that isn't,
+    /// since the stretch would pass through flattening the object on its way between them
+    pub fn new(keyframes: Vec<(f64, TransformData)>) -> Option<AnimatedTransform> {
+        assert!(!keyframes.is_empty(), "an animated transform needs at least one keyframe");
+        let mut keyframes: Vec<Keyframe> = keyframes.into_iter()
+            .map(|(time, transform)| AnimatedTransform::decompose(time, transform))
+            .collect();
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+        let mirrored = |keyframe: &Keyframe| keyframe.stretch.inverse_determinant() < 0.0;
+        if keyframes.windows(2).any(|pair| mirrored(&pair[0]) != mirrored(&pair[1])) {
+            return None;
+        }
+        Some(AnimatedTransform { keyframes })
+    }
+
+    /// Splits `transform` into translation, rotation and stretch (applied in the reverse order).
+    /// The rotation is found by polar decomposition of the linear part of the transform - repeatedly
+    /// averaging the matrix with its inverse transpose converges to the closest rotation
+    fn decompose(time: f64, transform: TransformData) -> Keyframe {
+        let matrix = transform.data;
+        let translation = Vec3(matrix[0][3], matrix[1][3], matrix[2][3]);
+
+        let mut linear = matrix;
+        for row in linear.iter_mut().take(3) {
+            row[3] = 0.0;
+        }
+        let mut rotation = linear;
+        for _ in 0..100 {
+            let inverse = TransformData::invert(rotation).expect("transforms are always invertible");
+            let mut next = rotation;
+            let mut change: f64 = 0.0;
+            for i in 0..3 {
+                for j in 0..3 {
+                    // inverse transpose
+                    next[i][j] = 0.5 * (rotation[i][j] + inverse[j][i]);
+                    change = change.max((next[i][j] - rotation[i][j]).abs());
+                }
+            }
+            rotation = next;
+            if change < 1e-12 {
+                break;
+            }
+        }
+
+        // mirroring can't be expressed as a rotation, so leave it in the stretch
+        let determinant = TransformData::from_matrix(rotation).map_or(1.0, |rotation| rotation.inverse_determinant());
+        if determinant < 0.0 {
+            for row in rotation.iter_mut().take(3) {
+                for value in row.iter_mut().take(3) {
+                    *value = -*value;
+                }
+            }
+        }
+
+        // rotation is orthonormal, so its transpose is its inverse
+        let mut rotation_transpose = rotation;
+        for (i, row) in rotation_transpose.iter_mut().enumerate().take(3) {
+            for (j, value) in row.iter_mut().enumerate().take(3) {
+                *value = rotation[j][i];
+            }
+        }
+        let stretch = TransformData::matmul(rotation_transpose, linear);
+        let stretch = TransformData::from_matrix(stretch).expect("transforms are always invertible");
+
+        Keyframe { time, translation, rotation: Quaternion::from_rotation_matrix(rotation), stretch, transform }
+    }
+
+    /// The transform at `time`. This is found for every ray, so nothing is inverted unless the stretch changes
+    /// between keyframes, and then only by the (cheap) adjugate
+    pub fn at(&self, time: f64) -> TransformData {
+        let first = &self.keyframes[0];
+        let last = &self.keyframes[self.keyframes.len() - 1];
+        if time <= first.time {
+            return first.transform;
+        }
+        if time >= last.time {
+            return last.transform;
+        }
+
+        let index = self.keyframes.partition_point(|keyframe| keyframe.time <= time);
+        let (start, end) = (&self.keyframes[index - 1], &self.keyframes[index]);
+        let t = (time - start.time) / (end.time - start.time);
+
+        let translation = (1.0 - t) * start.translation + t * end.translation;
+        let rotation = Quaternion::slerp(start.rotation, end.rotation, t);
+        let stretch = if start.stretch.data == end.stretch.data {
+            start.stretch
+        }
+        else {
+            // keyframes are never mirrored differently, so the interpolated stretch can't be singular
+            let mut stretch = start.stretch.data;
+            for (i, row) in stretch.iter_mut().enumerate() {
+                for (j, value) in row.iter_mut().enumerate() {
+                    *value = (1.0 - t) * start.stretch.data[i][j] + t * end.stretch.data[i][j];
+                }
+            }
+            TransformData { data: stretch, inverse: TransformData::invert_linear(stretch) }
+        };
+        stretch.rotate_quaternion(rotation).translate(translation)
+    }
+
+    /// Box around `bounding_box` as it moves from `t0` to `t1`. The motion is sampled at evenly spaced times,
+    /// and the result padded by how far a point could stray from a straight line while rotating between samples
+    pub fn motion_bounds(&self, bounding_box: AABB, t0: f64, t1: f64) -> AABB {
+        let mut times = vec![t0, t1];
+        for pair in self.keyframes.windows(2) {
+            let (start, end) = (pair[0].time.max(t0), pair[1].time.min(t1));
+            if start < end {
+                times.extend((0..=BOUNDING_STEPS).map(|i| start + (end - start) * i as f64 / BOUNDING_STEPS as f64));
+            }
+        }
+
+        let mut padding: f64 = 0.0;
+        for pair in self.keyframes.windows(2) {
+            let step_angle = Quaternion::angle_between(pair[0].rotation, pair[1].rotation) / BOUNDING_STEPS as f64;
+            padding = padding.max(1.0 - (0.5 * step_angle).cos());
+        }
+
+        let mut bounds: Option<AABB> = None;
+        let mut radius: f64 = 0.0;
+        for time in times {
+            let transform = self.at(time);
+            let origin = Vec3(0.0, 0.0, 0.0).transform(transform);
+            for corner in bounding_box.corners() {
+                let corner = corner.transform(transform);
+                radius = radius.max((corner - origin).length());
+                let point = AABB::new(corner, corner);
+                bounds = Some(bounds.map_or(point, |bounds| AABB::surrounding_box(bounds, point)));
+            }
+        }
+
+        let bounds = bounds.expect("there are always times to sample");
+        let padding = Vec3(1.0, 1.0, 1.0) * radius * padding;
+        AABB::new(bounds.minimum - padding, bounds.maximum + padding)
+    }
+
+    /// Transform at the time of the first keyframe
+    pub fn start(&self) -> TransformData {
+        self.keyframes[0].transform
+    }
 }
\ No newline at end of file
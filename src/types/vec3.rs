@@ -196,4 +196,17 @@ impl ops::Div<f64> for Vec3 {
     fn div(self, rhs: f64) -> Self::Output {
         self * (1.0 / rhs)
     }
+}
+
+// component by index, 0 = x, 1 = y, 2 = z
+impl ops::Index<usize> for Vec3 {
+    type Output = f64;
+    fn index(&self, index: usize) -> &Self::Output {
+        match index {
+            0 => &self.0,
+            1 => &self.1,
+            2 => &self.2,
+            _ => panic!("Vec3 index out of range: {}", index)
+        }
+    }
 }
\ No newline at end of file
@@ -33,6 +33,12 @@ pub mod perlin {
         ranvec: [Vec3; POINT_COUNT]
     }
 
+    impl Default for Perlin {
+        fn default() -> Self {
+            Perlin::new()
+        }
+    }
+
     impl Perlin {
         pub fn new() -> Perlin {
             let mut ranvec = [Vec3(0.0, 0.0, 0.0); POINT_COUNT];